@@ -1,42 +1,525 @@
-use std::str::FromStr;
+use std::{
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use clap::Args;
+use anyhow::Context;
+use clap::{ArgGroup, Args, Subcommand, value_parser};
 
-use crate::{CmdExecutor, cli::verify_file, process_csv};
+use crate::{
+    CmdExecutor, CsvDiffOptions, CsvJoinOptions, CsvOptions, CsvOutput, CsvReadOptions,
+    CsvShowRange, CsvSplitOptions, CsvStatsOptions, SplitBy, cli::verify_file, process_csv,
+    process_csv_diff, process_csv_from, process_csv_join, process_csv_show, process_csv_split,
+    process_csv_stats, process_csv_validate,
+};
 
+/// 不带子命令时就是原来的「CSV 转其他格式」，`-i` 只在这种情况下必填。
 #[derive(Debug, Args)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub(crate) struct CsvOpts {
-    #[arg(short, long, value_parser = verify_file)]
-    pub input: String,
+    #[command(subcommand)]
+    pub cmd: Option<CsvSubCommand>,
+
+    #[arg(short, long, value_parser = verify_file, required = true)]
+    pub input: Option<String>,
 
+    /// Output file, `-` for stdout; defaults to stdout when piped, else output.<format>
     #[arg(short, long)]
     pub output: Option<String>,
 
+    /// Write each record as its own document into this directory (<row>.<format>)
+    #[arg(long, conflicts_with = "output")]
+    pub out_dir: Option<PathBuf>,
+
     #[arg(long, value_parser = parse_format, default_value = "json")]
     pub format: OutputFormat,
 
+    /// Key of the TOML array of tables; defaults to the input file stem
+    #[arg(long)]
+    pub toml_root: Option<String>,
+
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+
+    /// Infer integer/float/boolean/date/null column types instead of emitting strings
+    #[arg(long, default_value_t = false)]
+    pub infer: bool,
+
+    /// Column → type overrides (JSON/YAML/TOML), e.g. `Kit Number: string`
+    #[arg(long, value_parser = verify_file)]
+    pub schema: Option<String>,
+
+    /// Columns to keep, in output order; header names or 1-based indices, comma-separated
+    #[arg(long, value_delimiter = ',')]
+    pub select: Vec<String>,
+
+    /// Columns to drop; header names or 1-based indices, comma-separated
+    #[arg(long, value_delimiter = ',')]
+    pub exclude: Vec<String>,
+
+    /// Rename a column, `old=new`; repeatable or comma-separated
+    #[arg(long, value_delimiter = ',')]
+    pub rename: Vec<String>,
+
+    /// Turn `address.city` and `tags[0]` style headers into nested objects and arrays
+    #[arg(long)]
+    pub nested: bool,
+
+    /// Only convert rows matching this expression, e.g. `Position == "Goalkeeper" && Kit Number <
+    /// 30`
+    #[arg(long = "where")]
+    pub filter: Option<String>,
+
+    /// Sort by these columns, `col` or `col:desc`, comma-separated; numbers compare numerically
+    #[arg(long, value_delimiter = ',')]
+    pub sort_by: Vec<String>,
+
+    /// Drop repeated rows, keeping the first; compare only these columns when given
+    #[arg(long, value_delimiter = ',', num_args = 0..)]
+    pub dedup: Option<Vec<String>>,
+
+    /// Memory budget for --sort-by in MiB; larger inputs are sorted through temporary files
+    #[arg(long, default_value_t = 256, value_parser = value_parser!(u64).range(1..))]
+    pub sort_memory: u64,
+}
+
+/// 读 CSV 的共用选项，所有读 CSV 的子命令都摊平进来，写法一致。没给的项自动探测。
+#[derive(Debug, Args)]
+pub(crate) struct CsvReadOpts {
+    /// Field delimiter; detected among `,` `;` tab `|` when omitted
+    #[arg(short, long)]
+    pub delimiter: Option<char>,
+
+    /// Quote character; detected (`"` or `'`) when omitted
+    #[arg(long)]
+    pub quote: Option<char>,
+
+    /// The first row is a header (detected when neither --header nor --no-header is given)
+    #[arg(long, conflicts_with = "no_header")]
+    pub header: bool,
+
+    /// The first row is data; columns are named col1, col2, ...
+    #[arg(long)]
+    pub no_header: bool,
+
+    /// Input encoding, e.g. utf-8, latin1, windows-1252, utf-16le; detected when omitted
+    #[arg(long)]
+    pub encoding: Option<String>,
+
+    /// Print the dialect actually used (delimiter, quote, header, encoding) to stderr
+    #[arg(long)]
+    pub print_dialect: bool,
+
+    /// Worksheet to read when the input is an xlsx/xls/ods workbook: a name or a 1-based index
+    #[arg(long)]
+    pub sheet: Option<String>,
+}
+
+impl From<CsvReadOpts> for CsvReadOptions {
+    fn from(opts: CsvReadOpts) -> Self {
+        Self {
+            delimiter: opts.delimiter,
+            quote: opts.quote,
+            has_header: match (opts.header, opts.no_header) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            encoding: opts.encoding,
+            print_dialect: opts.print_dialect,
+            sheet: opts.sheet,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum CsvSubCommand {
+    #[command(
+        name = "from",
+        about = "Convert JSON/NDJSON/YAML/TOML records back to CSV"
+    )]
+    From(CsvFromOpts),
+    #[command(name = "show", about = "Pretty-print CSV as a table in the terminal")]
+    Show(CsvShowOpts),
+    #[command(
+        name = "stats",
+        visible_alias = "group-by",
+        about = "Summarize columns, or aggregate rows with --group-by"
+    )]
+    Stats(CsvStatsOpts),
+    #[command(name = "join", about = "Join two CSV files on key columns")]
+    Join(CsvJoinOpts),
+    #[command(name = "diff", about = "Compare two versions of a CSV by key columns")]
+    Diff(CsvDiffOpts),
+    #[command(
+        name = "split",
+        about = "Split a CSV into parts by rows, bytes or column value"
+    )]
+    Split(CsvSplitOpts),
+    #[command(
+        name = "validate",
+        about = "Check a CSV against a schema of column rules"
+    )]
+    Validate(CsvValidateOpts),
+}
+
+impl_cmd_executor!(CsvSubCommand {
+    From,
+    Show,
+    Stats,
+    Join,
+    Diff,
+    Split,
+    Validate
+});
+
+#[derive(Debug, Args)]
+pub(crate) struct CsvFromOpts {
+    #[arg(short, long, value_parser = verify_file)]
+    pub input: String,
+
+    /// Output file, `-` for stdout
+    #[arg(short, long, default_value = "output.csv")]
+    pub output: String,
+
+    /// Input format; inferred from the input file extension when omitted
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+
     #[arg(short, long, default_value_t = ',')]
     pub delimiter: char,
 
+    /// Also expand arrays into `tags[0]`, `tags[1]` columns; the inverse of `csv --nested`
+    #[arg(long)]
+    pub nested: bool,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct CsvShowOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+
+    /// Show only the first N rows
+    #[arg(long, conflicts_with = "tail")]
+    pub head: Option<usize>,
+
+    /// Show only the last N rows
+    #[arg(long)]
+    pub tail: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct CsvStatsOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Output file, `-` for stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    #[arg(long, value_parser = parse_format, default_value = "json")]
+    pub format: OutputFormat,
+
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+
+    /// Group rows by these columns; header names or 1-based indices, comma-separated
+    #[arg(long, value_delimiter = ',')]
+    pub group_by: Vec<String>,
+
+    /// Aggregates per group: count, count/nulls/distinct/min/max/sum/mean(col)
+    #[arg(long, value_delimiter = ',', requires = "group_by")]
+    pub agg: Vec<String>,
+
+    /// Only count rows matching this expression, same syntax as `csv --where`
+    #[arg(long = "where")]
+    pub filter: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct CsvJoinOpts {
+    /// Left CSV, streamed row by row; put the larger file here
+    #[arg(value_parser = verify_file)]
+    pub left: String,
+
+    /// Right CSV, loaded into memory
+    #[arg(value_parser = verify_file)]
+    pub right: String,
+
+    /// Key columns, `Name` or `left=right` when the names differ; comma-separated
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub on: Vec<String>,
+
+    /// inner, left or full
+    #[arg(long, value_parser = parse_join_type, default_value = "inner")]
+    pub how: JoinType,
+
+    /// Suffix for left columns whose name also appears on the right
+    #[arg(long, default_value = "_left")]
+    pub left_suffix: String,
+
+    /// Suffix for right columns whose name also appears on the left
+    #[arg(long, default_value = "_right")]
+    pub right_suffix: String,
+
+    /// Output file, `-` for stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    #[arg(long, value_parser = parse_format, default_value = "json")]
+    pub format: OutputFormat,
+
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct CsvDiffOpts {
+    /// Old version, loaded into memory
+    #[arg(value_parser = verify_file)]
+    pub old: String,
+
+    /// New version, streamed row by row
+    #[arg(value_parser = verify_file)]
+    pub new: String,
+
+    /// Key columns identifying a row; header names or 1-based indices, comma-separated
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub key: Vec<String>,
+
+    /// Output file, `-` for stdout
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    /// Write one record per change in this format instead of the text report
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+}
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("by").required(true).args(["rows", "bytes", "column"])))]
+pub(crate) struct CsvSplitOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Directory for the parts; created if missing
+    #[arg(short, long)]
+    pub out_dir: PathBuf,
+
+    /// At most this many rows per part
+    #[arg(long)]
+    pub rows: Option<usize>,
+
+    /// At most this many bytes per part, header included: 500K, 10M, 1G
+    #[arg(long, value_parser = parse_size)]
+    pub bytes: Option<u64>,
+
+    /// One part per distinct value of this column; header name or 1-based index
+    #[arg(long = "by")]
+    pub column: Option<String>,
+
+    /// File name template: {stem}, {n} or zero-padded {n:04}, {value} with --by
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Overwrite parts that already exist in the output directory
     #[arg(long, default_value_t = false)]
-    pub no_header: bool,
+    pub force: bool,
+
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct CsvValidateOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Column rules (JSON/YAML/TOML): required, nullable, type, pattern, unique, allowed
+    #[arg(long, value_parser = verify_file)]
+    pub schema: String,
+
+    /// Print the report as JSON for CI
+    #[arg(long, default_value_t = false)]
+    pub json: bool,
+
+    #[command(flatten)]
+    pub read: CsvReadOpts,
 }
 
 impl CmdExecutor for CsvOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let output = if let Some(output) = self.output {
-            output
-        } else {
-            format!("output.{}", self.format)
+        if let Some(cmd) = self.cmd {
+            return cmd.execute().await;
+        }
+        let input = self.input.context("--input is required")?;
+        let output = match (self.out_dir, self.output) {
+            (Some(dir), _) => CsvOutput::Dir(dir),
+            (None, Some(output)) if output == "-" => CsvOutput::Stdout,
+            (None, Some(output)) => CsvOutput::File(output.into()),
+            // 被管道接走时默认写标准输出，在终端里才落到 output.<格式>
+            (None, None) if !io::stdout().is_terminal() => CsvOutput::Stdout,
+            (None, None) => CsvOutput::File(format!("output.{}", self.format).into()),
+        };
+        let opts = CsvOptions {
+            read: self.read.into(),
+            infer: self.infer,
+            schema: self.schema,
+            toml_root: self.toml_root,
+            select: self.select,
+            exclude: self.exclude,
+            rename: self.rename,
+            filter: self.filter,
+            nested: self.nested,
+            sort_by: self.sort_by,
+            dedup: self.dedup,
+            sort_memory: Some(
+                usize::try_from(self.sort_memory.saturating_mul(1024 * 1024)).unwrap_or(usize::MAX),
+            ),
+        };
+        process_csv(&input, output, self.format, &opts).await?;
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvFromOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let format = match self.format {
+            Some(format) => format,
+            None => Path::new(&self.input)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .with_context(|| format!("cannot infer format of {:?}, pass --format", self.input))?
+                .parse()?,
         };
-        process_csv(
+        process_csv_from(
             &self.input,
-            output,
-            self.format,
+            self.output,
+            format,
             self.delimiter,
-            !self.no_header,
+            self.nested,
         )
-        .await?;
+        .await
+    }
+}
+
+impl CmdExecutor for CsvShowOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let range = match (self.head, self.tail) {
+            (Some(n), _) => CsvShowRange::Head(n),
+            (None, Some(n)) => CsvShowRange::Tail(n),
+            (None, None) => CsvShowRange::All,
+        };
+        // 只有输出到终端才按终端宽度截断，重定向到文件或管道时保留完整内容
+        let max_width = io::stdout()
+            .is_terminal()
+            .then(terminal_size::terminal_size)
+            .flatten()
+            .map(|(width, _)| usize::from(width.0));
+        let table = process_csv_show(&self.input, &self.read.into(), range, max_width).await?;
+        print!("{table}");
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvStatsOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let opts = CsvStatsOptions {
+            read: self.read.into(),
+            group_by: self.group_by,
+            agg: self.agg,
+            filter: self.filter,
+        };
+        let stats = process_csv_stats(&self.input, self.format, &opts).await?;
+        if self.output == "-" {
+            print!("{stats}");
+        } else {
+            tokio::fs::write(&self.output, stats).await?;
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvJoinOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let opts = CsvJoinOptions {
+            read: self.read.into(),
+            on: self.on,
+            how: self.how,
+            left_suffix: self.left_suffix,
+            right_suffix: self.right_suffix,
+        };
+        process_csv_join(&self.left, &self.right, &self.output, self.format, &opts).await
+    }
+}
+
+impl CmdExecutor for CsvDiffOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let opts = CsvDiffOptions {
+            read: self.read.into(),
+            key: self.key,
+        };
+        let diff = process_csv_diff(&self.old, &self.new, &opts).await?;
+        let text = match self.format {
+            Some(format) => diff.render(format).await?,
+            None => diff.to_string(),
+        };
+        if self.output == "-" {
+            print!("{text}");
+        } else {
+            tokio::fs::write(&self.output, text).await?;
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvSplitOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let by = match (self.rows, self.bytes, self.column) {
+            (Some(rows), _, _) => SplitBy::Rows(rows),
+            (_, Some(bytes), _) => SplitBy::Bytes(bytes),
+            (_, _, Some(column)) => SplitBy::Column(column),
+            // clap 的参数组保证三个里恰好给了一个
+            (None, None, None) => unreachable!("one of --rows, --bytes, --by is required"),
+        };
+        let opts = CsvSplitOptions {
+            read: self.read.into(),
+            by,
+            name: self.name,
+            force: self.force,
+        };
+        for part in process_csv_split(&self.input, &self.out_dir, &opts).await? {
+            println!("{}\t{}", part.path.display(), part.rows);
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvValidateOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let report = process_csv_validate(&self.input, &self.schema, &self.read.into()).await?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            for violation in &report.violations {
+                println!("{violation}");
+            }
+        }
+        // 有违规就以非零退出，CI 里直接挡住
+        if !report.valid {
+            anyhow::bail!(
+                "{} violation(s) in {} row(s)",
+                report.violations.len(),
+                report.rows
+            );
+        }
         Ok(())
     }
 }
@@ -44,11 +527,20 @@ async fn execute(self) -> anyhow::Result<()> {
 #[derive(Debug, Clone, Copy)]
 pub enum OutputFormat {
     Json,
+    /// 每行一条紧凑 JSON（JSON Lines）
+    Ndjson,
     Toml,
     Yaml,
+    Csv,
+    /// 二进制列式格式，只能写到文件或管道
+    Parquet,
+    /// Arrow IPC 文件格式（Feather v2）
+    Arrow,
+    /// Excel 工作簿，一个工作表
+    Xlsx,
 }
 
-fn parse_format(format: &str) -> Result<OutputFormat, anyhow::Error> {
+pub(crate) fn parse_format(format: &str) -> Result<OutputFormat, anyhow::Error> {
     format.parse()
 }
 
@@ -56,8 +548,13 @@ impl From<OutputFormat> for &'static str {
     fn from(value: OutputFormat) -> Self {
         match value {
             OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
             OutputFormat::Toml => "toml",
             OutputFormat::Yaml => "yaml",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Arrow => "arrow",
+            OutputFormat::Xlsx => "xlsx",
         }
     }
 }
@@ -68,15 +565,79 @@ impl FromStr for OutputFormat {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
             "json" => Ok(OutputFormat::Json),
-            "yaml" => Ok(OutputFormat::Yaml),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            "yaml" | "yml" => Ok(OutputFormat::Yaml),
             "toml" => Ok(OutputFormat::Toml),
+            "csv" => Ok(OutputFormat::Csv),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "arrow" | "feather" | "ipc" => Ok(OutputFormat::Arrow),
+            "xlsx" => Ok(OutputFormat::Xlsx),
             v => anyhow::bail!("Unsupported format: {v}"),
         }
     }
 }
 
+impl OutputFormat {
+    /// 按批编码的列式格式。
+    pub(crate) fn is_columnar(self) -> bool {
+        matches!(self, OutputFormat::Parquet | OutputFormat::Arrow)
+    }
+
+    /// 二进制格式：写不成字符串，也没法逐条流式写。
+    pub(crate) fn is_binary(self) -> bool {
+        self.is_columnar() || matches!(self, OutputFormat::Xlsx)
+    }
+}
+
 impl std::fmt::Display for OutputFormat {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", Into::<&str>::into(*self))
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinType {
+    Inner,
+    Left,
+    /// 全外连接：两边没匹配上的行都保留
+    Full,
+}
+
+/// `1048576`、`500K`、`10M`、`1G`，单位按 1024 进位，大小写都行，可以带 `B` / `iB`。
+fn parse_size(size: &str) -> Result<u64, anyhow::Error> {
+    let size = size.trim();
+    let digits = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(digits);
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("invalid size {size:?}"))?;
+    let shift = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        _ => anyhow::bail!("invalid size {size:?}, expected e.g. 500K, 10M or 1G"),
+    };
+    number
+        .checked_mul(1 << shift)
+        .with_context(|| format!("size {size:?} is too large"))
+}
+
+fn parse_join_type(join: &str) -> Result<JoinType, anyhow::Error> {
+    join.parse()
+}
+
+impl FromStr for JoinType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "inner" => Ok(JoinType::Inner),
+            "left" => Ok(JoinType::Left),
+            "full" | "outer" => Ok(JoinType::Full),
+            v => anyhow::bail!("Unsupported join type: {v}"),
+        }
+    }
+}
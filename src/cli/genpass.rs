@@ -1,16 +1,44 @@
-use clap::{Args, value_parser};
-use zxcvbn::zxcvbn;
+use std::{fmt, str::FromStr};
 
-use crate::{CmdExecutor, process_genpass};
+use anyhow::Context;
+use clap::{Args, Subcommand, value_parser};
+use serde::Deserialize;
+use tokio::io::{self, BufWriter};
+
+use crate::{
+    CmdExecutor, GenPassOptions, GeneratedPassword, OutputFormat, PassphraseOptions,
+    PasswordPolicy, Wordlist,
+    cli::{csv::parse_format, verify_file},
+    process_genpass, process_passphrase, process_password_check, records_to_string,
+    write_generated,
+};
 
 #[expect(
     clippy::struct_excessive_bools,
     reason = "CLI 开关天然就是一组彼此独立的 bool 标志，拆成枚举反而更难用"
 )]
 #[derive(Debug, Args)]
+#[command(args_conflicts_with_subcommands = true)]
 pub(crate) struct GenPassOpts {
-    #[arg(short, long, default_value_t = 16, value_parser = value_parser!(u8).range(8..))]
-    pub length: u8,
+    #[command(subcommand)]
+    pub cmd: Option<GenPassSubCommand>,
+
+    /// Password length, 8 to 4096 [default: 16]. With --policy it must fit the policy's length
+    /// range instead, and defaults to the policy's own length
+    #[arg(short, long, value_parser = value_parser!(u16).range(1..=4096))]
+    pub length: Option<u16>,
+
+    /// Follow a password policy: a built-in profile (aws-iam, active-directory, oracle,
+    /// mainframe) or a YAML / TOML / JSON policy file
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "no_uppercase", "no_lowercase", "no_number", "no_symbol", "symbols", "charset",
+            "exclude", "allow_ambiguous", "min_upper", "min_lower", "min_digit", "min_symbol",
+            "start_with", "max_run",
+        ]
+    )]
+    pub policy: Option<String>,
 
     #[arg(long, default_value_t = false)]
     pub no_uppercase: bool,
@@ -23,21 +51,341 @@ pub(crate) struct GenPassOpts {
 
     #[arg(long, default_value_t = false)]
     pub no_symbol: bool,
+
+    /// Symbols to draw from instead of the default `!@#$%^&*_`
+    #[arg(long, allow_hyphen_values = true)]
+    pub symbols: Option<String>,
+
+    /// Draw from exactly these characters instead of the four classes
+    #[arg(
+        long,
+        allow_hyphen_values = true,
+        conflicts_with_all = ["no_uppercase", "no_lowercase", "no_number", "no_symbol", "symbols"]
+    )]
+    pub charset: Option<String>,
+
+    /// Never use these characters
+    #[arg(long, default_value = "", allow_hyphen_values = true)]
+    pub exclude: String,
+
+    /// Keep look-alike characters `0 O I l` in the built-in classes, which drop them by default;
+    /// --charset is always used as given
+    #[arg(long, default_value_t = false)]
+    pub allow_ambiguous: bool,
+
+    /// At least this many uppercase letters; defaults to 1 when any are available
+    #[arg(long)]
+    pub min_upper: Option<usize>,
+
+    /// At least this many lowercase letters; defaults to 1 when any are available
+    #[arg(long)]
+    pub min_lower: Option<usize>,
+
+    /// At least this many digits; defaults to 1 when any are available
+    #[arg(long)]
+    pub min_digit: Option<usize>,
+
+    /// At least this many symbols; defaults to 1 when any are available
+    #[arg(long)]
+    pub min_symbol: Option<usize>,
+
+    /// The first character must be upper, lower, letter, digit, alphanumeric or symbol
+    #[arg(long, value_parser = parse_char_class)]
+    pub start_with: Option<CharClass>,
+
+    /// Never repeat the same character more than this many times in a row
+    #[arg(long, value_parser = value_parser!(u8).range(1..))]
+    pub max_run: Option<u8>,
+
+    /// Generate this many passwords
+    #[arg(short = 'n', long, default_value_t = 1, value_parser = value_parser!(u64).range(1..))]
+    pub count: u64,
+
+    /// Print each password with its zxcvbn score, entropy and crack time as json, ndjson, yaml,
+    /// toml or csv; plain lines when omitted
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+
+    /// Generate a passphrase of this many words instead of characters
+    #[arg(
+        long,
+        requires = "wordlist",
+        conflicts_with_all = [
+            "length", "policy", "no_uppercase", "no_lowercase", "no_number", "no_symbol",
+            "symbols", "charset", "exclude", "allow_ambiguous", "min_upper", "min_lower",
+            "min_digit", "min_symbol", "start_with", "max_run",
+        ]
+    )]
+    pub words: Option<usize>,
+
+    /// Wordlist for --words: EFF format (`11111<Tab>abacus`) or one word per line
+    #[arg(long, value_parser = verify_file, requires = "words")]
+    pub wordlist: Option<String>,
+
+    /// Put this between the words
+    #[arg(long, default_value = "-", requires = "words")]
+    pub separator: String,
+
+    /// lower, title, upper, or random (title-case each word by coin flip)
+    #[arg(long, value_parser = parse_capitalize, default_value = "lower", requires = "words")]
+    pub capitalize: Capitalize,
+
+    /// Append a random digit or symbol to the passphrase
+    #[arg(long, value_parser = parse_suffix, requires = "words")]
+    pub append: Option<PassphraseSuffix>,
 }
 
-#[expect(clippy::unused_async_trait_impl)]
-impl CmdExecutor for GenPassOpts {
+/// 不带子命令时生成密码。
+#[derive(Debug, Subcommand)]
+pub(crate) enum GenPassSubCommand {
+    #[command(
+        name = "check",
+        about = "Check the strength of passwords read from a file or stdin, one per line"
+    )]
+    Check(GenPassCheckOpts),
+}
+
+impl_cmd_executor!(GenPassSubCommand { Check });
+
+#[derive(Debug, Args)]
+pub(crate) struct GenPassCheckOpts {
+    /// Candidate passwords, one per line; `-` for stdin
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Words tied to the account, such as username, email or real name; passwords containing
+    /// them score lower
+    #[arg(short, long = "user-input", value_name = "WORD", value_delimiter = ',')]
+    pub user_inputs: Vec<String>,
+
+    /// Exit with an error when any password scores below this (0-4)
+    #[arg(long, value_parser = value_parser!(u8).range(0..=4))]
+    pub min_score: Option<u8>,
+
+    /// Print one record per password as json, ndjson, yaml, toml or csv instead of the text report
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+}
+
+impl CmdExecutor for GenPassCheckOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let password = process_genpass(
-            self.length,
-            self.no_uppercase,
-            self.no_lowercase,
-            self.no_number,
-            self.no_symbol,
-        )?;
-        println!("{password}");
-        let result = zxcvbn(&password, &[]);
-        eprintln!("Password strength: {}", result.score());
+        let reports = process_password_check(&self.input, &self.user_inputs).await?;
+        if let Some(format) = self.format {
+            print!("{}", records_to_string(format, &reports).await?);
+        } else {
+            for report in &reports {
+                print!("{report}");
+            }
+        }
+        // 有密码不达标就以非零退出，入职脚本里直接挡住
+        if let Some(min_score) = self.min_score {
+            let weak: Vec<String> = reports
+                .iter()
+                .filter(|report| report.score < min_score)
+                .map(|report| report.line.to_string())
+                .collect();
+            if !weak.is_empty() {
+                anyhow::bail!(
+                    "{} of {} password(s) scored below {min_score} (line {})",
+                    weak.len(),
+                    reports.len(),
+                    weak.join(", ")
+                );
+            }
+        }
         Ok(())
     }
 }
+
+/// 不带 `--policy` 时 `-l` 的下限；带了就按策略的长度范围检查。
+const MIN_LENGTH: usize = 8;
+
+/// 每调用一次生成一个密码，连同它的熵。
+type Generator = Box<dyn Fn() -> anyhow::Result<(String, f64)>>;
+
+impl CmdExecutor for GenPassOpts {
+    async fn execute(mut self) -> anyhow::Result<()> {
+        if let Some(cmd) = self.cmd.take() {
+            return cmd.execute().await;
+        }
+        let (count, format) = (self.count, self.format);
+        let generate = self.generator()?;
+        if count == 1 && format.is_none() {
+            let (password, entropy) = generate()?;
+            let entry = GeneratedPassword::new(password, entropy);
+            println!("{}", entry.password);
+            eprintln!("Password strength: {}", entry.score);
+            eprintln!("Entropy: {:.1} bits", entry.entropy);
+            return Ok(());
+        }
+        write_generated(BufWriter::new(io::stdout()), count, format, generate).await?;
+        Ok(())
+    }
+}
+
+impl GenPassOpts {
+    fn generator(self) -> anyhow::Result<Generator> {
+        if let (Some(words), Some(wordlist)) = (self.words, &self.wordlist) {
+            let wordlist = Wordlist::load(wordlist)?;
+            let opts = PassphraseOptions {
+                words,
+                separator: self.separator,
+                capitalize: self.capitalize,
+                suffix: self.append,
+            };
+            return Ok(Box::new(move || {
+                let passphrase = process_passphrase(&wordlist, &opts)?;
+                Ok((passphrase.phrase, passphrase.entropy))
+            }));
+        }
+        if let Some(policy) = self.policy {
+            let opts = PasswordPolicy::load(&policy)?.options(self.length.map(usize::from))?;
+            let entropy = opts.entropy()?;
+            return Ok(Box::new(move || {
+                let password = process_genpass(&opts)
+                    .with_context(|| format!("policy {policy} can't be satisfied"))?;
+                Ok((password, entropy))
+            }));
+        }
+        let length = self.length.map_or(16, usize::from);
+        if length < MIN_LENGTH {
+            anyhow::bail!("length {length} is too short, use at least {MIN_LENGTH} or a --policy");
+        }
+        let opts = GenPassOptions {
+            length,
+            upper: !self.no_uppercase,
+            lower: !self.no_lowercase,
+            digit: !self.no_number,
+            symbol: !self.no_symbol,
+            symbols: self.symbols,
+            charset: self.charset,
+            exclude: self.exclude,
+            allow_ambiguous: self.allow_ambiguous,
+            min_upper: self.min_upper,
+            min_lower: self.min_lower,
+            min_digit: self.min_digit,
+            min_symbol: self.min_symbol,
+            start_with: self.start_with,
+            max_run: self.max_run.map(usize::from),
+        };
+        let entropy = opts.entropy()?;
+        Ok(Box::new(move || Ok((process_genpass(&opts)?, entropy))))
+    }
+}
+
+/// 口令里每个词的大小写。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capitalize {
+    /// 保持词表里的样子
+    Lower,
+    /// 每个词首字母大写
+    Title,
+    Upper,
+    /// 每个词各自随机决定首字母大不大写，每个词多 1 bit 熵
+    Random,
+}
+
+/// 一类字符，用在「必须以某类字符开头」这样的规则里。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CharClass {
+    Upper,
+    Lower,
+    /// 大写或小写字母
+    Letter,
+    Digit,
+    /// 字母或数字
+    Alphanumeric,
+    /// 字母数字以外的字符
+    Symbol,
+}
+
+impl CharClass {
+    pub fn contains(self, c: char) -> bool {
+        match self {
+            CharClass::Upper => c.is_uppercase(),
+            CharClass::Lower => c.is_lowercase(),
+            CharClass::Letter => c.is_alphabetic(),
+            CharClass::Digit => c.is_numeric(),
+            CharClass::Alphanumeric => c.is_alphanumeric(),
+            CharClass::Symbol => !c.is_alphanumeric(),
+        }
+    }
+}
+
+/// 口令末尾补的字符。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassphraseSuffix {
+    Digit,
+    Symbol,
+}
+
+fn parse_capitalize(capitalize: &str) -> Result<Capitalize, anyhow::Error> {
+    capitalize.parse()
+}
+
+fn parse_suffix(suffix: &str) -> Result<PassphraseSuffix, anyhow::Error> {
+    suffix.parse()
+}
+
+fn parse_char_class(class: &str) -> Result<CharClass, anyhow::Error> {
+    class.parse()
+}
+
+impl FromStr for Capitalize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "lower" | "none" => Ok(Capitalize::Lower),
+            "title" => Ok(Capitalize::Title),
+            "upper" => Ok(Capitalize::Upper),
+            "random" => Ok(Capitalize::Random),
+            v => anyhow::bail!("Unsupported capitalization: {v}"),
+        }
+    }
+}
+
+impl FromStr for PassphraseSuffix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "digit" | "number" => Ok(PassphraseSuffix::Digit),
+            "symbol" => Ok(PassphraseSuffix::Symbol),
+            v => anyhow::bail!("Unsupported suffix: {v}, expected digit or symbol"),
+        }
+    }
+}
+
+impl FromStr for CharClass {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "upper" => Ok(CharClass::Upper),
+            "lower" => Ok(CharClass::Lower),
+            "letter" => Ok(CharClass::Letter),
+            "digit" | "number" => Ok(CharClass::Digit),
+            "alphanumeric" => Ok(CharClass::Alphanumeric),
+            "symbol" => Ok(CharClass::Symbol),
+            v => anyhow::bail!(
+                "Unsupported character class: {v}, expected upper, lower, letter, digit, \
+                 alphanumeric or symbol"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CharClass::Upper => "an uppercase letter",
+            CharClass::Lower => "a lowercase letter",
+            CharClass::Letter => "a letter",
+            CharClass::Digit => "a digit",
+            CharClass::Alphanumeric => "a letter or digit",
+            CharClass::Symbol => "a symbol",
+        })
+    }
+}
@@ -12,7 +12,8 @@
 
 pub use self::{
     base64::Base64Format,
-    csv::OutputFormat,
+    csv::{JoinType, OutputFormat},
+    genpass::{Capitalize, CharClass, PassphraseSuffix},
     text::{TextKeyFormat, TextSignFormat},
 };
 pub(crate) use self::{
@@ -30,10 +31,13 @@ pub struct Opts {
 #[derive(Debug, Subcommand)]
 pub(crate) enum SubCommand {
     #[command(name = "csv", about = "Show CSV, or convert CSV to other format")]
-    Csv(CsvOpts),
+    Csv(Box<CsvOpts>),
 
-    #[command(name = "genpass", about = "Generate a random password")]
-    GenPass(GenPassOpts),
+    #[command(
+        name = "genpass",
+        about = "Generate a random password, or check password strength"
+    )]
+    GenPass(Box<GenPassOpts>),
 
     #[command(subcommand)]
     Base64(Base64SubCommand),
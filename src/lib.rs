@@ -4,8 +4,8 @@
 //!
 //! | 命令 | 做什么 |
 //! |---|---|
-//! | `csv` | 读 CSV，转成 JSON / YAML / TOML |
-//! | `genpass` | 生成随机密码，并用 zxcvbn 评估强度 |
+//! | `csv` | 读 CSV，转成 JSON / NDJSON / YAML / TOML；`csv from` 反过来把记录数组还原成 CSV；`csv show` / `stats` / `join` / `diff` / `split` / `validate` 看表格、做汇总、拼表、对比、切分、校验 |
+//! | `genpass` | 生成随机密码或 diceware 口令，可按目标系统的策略批量生成；`genpass check` 用 zxcvbn 检查已有密码的强度 |
 //! | `base64` | 标准与 URL-safe 两种字母表的编解码 |
 //! | `text` | Blake3 签名验签、Ed25519 签名验签、ChaCha20-Poly1305 加解密 |
 //! | `http` | 起一个静态文件服务，或为目录批量生成 index.html |
@@ -25,11 +25,40 @@
 //!
 //! ```
 //! # fn main() -> anyhow::Result<()> {
-//! let password = rcli::process_genpass(16, false, false, false, false)?;
+//! let password = rcli::process_genpass(&rcli::GenPassOptions::default())?;
 //! assert_eq!(password.len(), 16);
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! CSV 可以直接读成自己的类型，出错时 [`CsvError`] 带着行号；转换结果可以写进任意
+//! `AsyncWrite`（[`convert_csv`]、[`write_records`]）或者拿回字符串：
+//!
+//! ```
+//! # #[tokio::main]
+//! # async fn main() -> anyhow::Result<()> {
+//! use rcli::{CsvReadOptions, CsvReader, OutputFormat, records_to_string};
+//!
+//! #[derive(serde::Deserialize, serde::Serialize)]
+//! struct Player {
+//!     #[serde(rename = "Name")]
+//!     name: String,
+//!     #[serde(rename = "Kit Number")]
+//!     kit: u8,
+//! }
+//!
+//! let mut reader =
+//!     CsvReader::<Player>::open("assets/juventus.csv", &CsvReadOptions::default())
+//!         .await?;
+//! let mut players = Vec::new();
+//! while let Some(player) = reader.next().await {
+//!     players.push(player?);
+//! }
+//! let yaml = records_to_string(OutputFormat::Yaml, &players[..1]).await?;
+//! assert_eq!(yaml, "- Name: Wojciech Szczesny\n  Kit Number: 1\n");
+//! # Ok(())
+//! # }
+//! ```
 
 #[macro_use]
 mod macros;
@@ -38,12 +67,21 @@
 mod utils;
 
 use anyhow::Result;
-pub use cli::{Base64Format, Opts, OutputFormat, TextKeyFormat, TextSignFormat};
+pub use cli::{
+    Base64Format, Capitalize, CharClass, JoinType, Opts, OutputFormat, PassphraseSuffix,
+    TextKeyFormat, TextSignFormat,
+};
 pub use process::{
-    Claims, process_csv, process_decode, process_encode, process_genpass, process_http_index,
-    process_http_serve, process_jwt_pubkey, process_jwt_sign, process_jwt_verify,
-    process_text_decrypt, process_text_encrypt, process_text_generate, process_text_sign,
-    process_text_verify,
+    Change, Claims, CsvDiff, CsvDiffOptions, CsvError, CsvJoinOptions, CsvOptions, CsvOutput,
+    CsvReadOptions, CsvReader, CsvShowRange, CsvSplitOptions, CsvStatsOptions, CsvValidationReport,
+    FieldChange, GenPassOptions, GeneratedPassword, Passphrase, PassphraseOptions, PasswordPolicy,
+    SplitBy, SplitPart, StrengthReport, Violation, Wordlist, convert_csv, convert_csv_to_string,
+    process_csv, process_csv_diff, process_csv_from, process_csv_join, process_csv_show,
+    process_csv_split, process_csv_stats, process_csv_validate, process_decode, process_encode,
+    process_genpass, process_http_index, process_http_serve, process_jwt_pubkey, process_jwt_sign,
+    process_jwt_verify, process_passphrase, process_password_check, process_text_decrypt,
+    process_text_encrypt, process_text_generate, process_text_sign, process_text_verify,
+    records_to_string, write_generated, write_records,
 };
 pub use utils::{InputReader, get_reader, write_secret};
 
@@ -51,6 +89,13 @@ pub(crate) trait CmdExecutor {
     async fn execute(self) -> Result<()>;
 }
 
+/// 参数多的子命令装箱放进枚举，免得整个枚举跟着变大；装箱后照样转发。
+impl<T: CmdExecutor> CmdExecutor for Box<T> {
+    async fn execute(self) -> Result<()> {
+        (*self).execute().await
+    }
+}
+
 pub async fn parse_cmd(opts: Opts) -> Result<()> {
     opts.cmd.execute().await
 }
@@ -0,0 +1,541 @@
+//! Parquet / Arrow IPC（Feather v2）：列式格式的写出与读回。
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Cursor, Lines, Read, Seek, Write},
+    mem,
+    sync::Arc,
+};
+
+use anyhow::{Context, Result, bail};
+use arrow_array::{
+    ArrayRef, BooleanArray, Date32Array, Float64Array, Int64Array, RecordBatch, RecordBatchReader,
+    StringArray, TimestampMicrosecondArray,
+};
+use arrow_cast::display::{ArrayFormatter, FormatOptions};
+use arrow_ipc::{
+    reader::{FileReader, StreamReader},
+    writer::FileWriter,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use bytes::Bytes;
+use csv::WriterBuilder;
+use parquet::{
+    arrow::{ArrowWriter, arrow_reader::ParquetRecordBatchReaderBuilder},
+    basic::Compression,
+    file::properties::WriterProperties,
+};
+use serde_json::Value;
+use tokio::task;
+
+use crate::{
+    cli::OutputFormat,
+    process::{
+        csv_convert::{cell, keys},
+        csv_infer::parse_date,
+    },
+};
+
+/// 一批多少行：收尾时从临时文件里每读这么多行编码一次。
+pub(crate) const BATCH_ROWS: usize = 8192;
+
+/// Arrow IPC 文件格式开头的魔数；不是它就按流格式读。
+const ARROW_MAGIC: &[u8; 6] = b"ARROW1";
+
+/// 看完整个输入才定每列的类型，所以记录先逐条写进临时文件（一行一条 JSON），同时累计类型；
+/// [`finish`](Self::finish) 时再按批读回来编码。
+///
+/// - 整数 → `Int64`，出现小数 → `Float64`，布尔 → `Boolean`
+/// - 日期列全是纯日期 → `Date32`，带时间的 → 微秒精度的 UTC `Timestamp`，没写时区的按 UTC 算
+/// - 其余、整列为空的，以及类型对不上的（整数列里冒出 `abc`）→ `Utf8`，不会写到一半才报错
+///
+/// 不推断时每个值都是字符串，所以每列都是 `Utf8`。
+#[derive(Debug)]
+pub(crate) struct ColumnarWriter {
+    format: OutputFormat,
+    dates: Vec<String>,
+    columns: Option<Vec<String>>,
+    /// 和 `columns` 一一对应；全是空值的列还是 `None`
+    kinds: Vec<Option<Kind>>,
+    /// 第一条记录到来才建
+    spool: Option<BufWriter<File>>,
+}
+
+enum Encoder {
+    Parquet(ArrowWriter<Vec<u8>>),
+    Arrow(FileWriter<Vec<u8>>),
+}
+
+impl std::fmt::Debug for Encoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Parquet(_) => "Parquet",
+            Self::Arrow(_) => "Arrow",
+        })
+    }
+}
+
+/// 一列最终写成什么类型。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Integer,
+    Float,
+    Boolean,
+    Date,
+    Timestamp,
+    Text,
+}
+
+impl ColumnarWriter {
+    /// `columns` 为 `None` 时取第一条记录的键；`dates` 是日期列的列名。
+    pub(crate) fn new(
+        format: OutputFormat,
+        columns: Option<Vec<String>>,
+        dates: Vec<String>,
+    ) -> Self {
+        Self {
+            format,
+            dates,
+            columns,
+            kinds: Vec::new(),
+            spool: None,
+        }
+    }
+
+    /// 收下一条记录，先记进临时文件。
+    pub(crate) fn push(&mut self, record: &Value) -> Result<()> {
+        let columns = self.columns.get_or_insert_with(|| keys(record));
+        self.kinds.resize(columns.len(), None);
+        for (kind, name) in self.kinds.iter_mut().zip(columns.iter()) {
+            if let Some(this) = record
+                .get(name)
+                .and_then(|value| value_kind(value, self.dates.contains(name)))
+            {
+                *kind = Some(kind.map_or(this, |kind| widen(kind, this)));
+            }
+        }
+        let spool = match &mut self.spool {
+            Some(spool) => spool,
+            None => self
+                .spool
+                .insert(BufWriter::new(tempfile::tempfile().context(
+                    "failed to create a temporary file for columnar output",
+                )?)),
+        };
+        serde_json::to_writer(&mut *spool, record)?;
+        spool.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// 类型定下来了，返回逐批编好的字节，最后一块是文件尾。一条记录都没有时按已知的列名写一个只有
+    /// schema 的空文件。
+    pub(crate) fn finish(mut self) -> Result<ColumnarChunks> {
+        let columns = self.columns.take().unwrap_or_default();
+        self.kinds.resize(columns.len(), None);
+        let kinds: Vec<Kind> = self
+            .kinds
+            .iter()
+            .map(|kind| kind.unwrap_or(Kind::Text))
+            .collect();
+        let schema = schema(&columns, &kinds);
+        let lines = match self.spool {
+            Some(spool) => {
+                let mut file = spool.into_inner().map_err(io::IntoInnerError::into_error)?;
+                file.rewind()?;
+                Some(BufReader::new(file).lines())
+            }
+            None => None,
+        };
+        Ok(ColumnarChunks {
+            encoder: Some(encoder(self.format, schema.clone())?),
+            schema,
+            lines,
+            written: 0,
+        })
+    }
+}
+
+/// [`ColumnarWriter::finish`] 的结果：每次给出一块编好的字节。
+#[derive(Debug)]
+pub(crate) struct ColumnarChunks {
+    schema: SchemaRef,
+    lines: Option<Lines<BufReader<File>>>,
+    /// 文件尾交出去之后为 `None`
+    encoder: Option<Encoder>,
+    written: usize,
+}
+
+impl Iterator for ColumnarChunks {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let encoder = self.encoder.take()?;
+        Some(self.chunk(encoder))
+    }
+}
+
+impl ColumnarChunks {
+    /// 读回下一批编码；读完了就写文件尾。
+    fn chunk(&mut self, mut encoder: Encoder) -> Result<Vec<u8>> {
+        let mut rows = Vec::new();
+        if let Some(lines) = &mut self.lines {
+            for line in lines.by_ref().take(BATCH_ROWS) {
+                rows.push(serde_json::from_str::<Value>(&line?)?);
+            }
+        }
+        if rows.is_empty() {
+            return Ok(match encoder {
+                Encoder::Parquet(writer) => writer.into_inner()?,
+                Encoder::Arrow(writer) => writer.into_inner()?,
+            });
+        }
+
+        let arrays = self
+            .schema
+            .fields()
+            .iter()
+            .map(|field| array(field, &rows, self.written))
+            .collect::<Result<Vec<_>>>()?;
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays)?;
+        self.written += rows.len();
+        let bytes = match &mut encoder {
+            Encoder::Parquet(writer) => {
+                writer.write(&batch)?;
+                mem::take(writer.inner_mut())
+            }
+            Encoder::Arrow(writer) => {
+                writer.write(&batch)?;
+                mem::take(writer.get_mut())
+            }
+        };
+        self.encoder = Some(encoder);
+        Ok(bytes)
+    }
+}
+
+fn encoder(format: OutputFormat, schema: SchemaRef) -> Result<Encoder> {
+    Ok(match format {
+        OutputFormat::Parquet => {
+            let props = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build();
+            Encoder::Parquet(ArrowWriter::try_new(Vec::new(), schema, Some(props))?)
+        }
+        OutputFormat::Arrow => Encoder::Arrow(FileWriter::try_new(Vec::new(), &schema)?),
+        format => bail!("{format} is not a columnar format"),
+    })
+}
+
+fn schema(columns: &[String], kinds: &[Kind]) -> SchemaRef {
+    let fields: Vec<Field> = columns
+        .iter()
+        .zip(kinds)
+        .map(|(name, kind)| {
+            let data_type = match kind {
+                Kind::Integer => DataType::Int64,
+                Kind::Float => DataType::Float64,
+                Kind::Boolean => DataType::Boolean,
+                Kind::Date => DataType::Date32,
+                Kind::Timestamp => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+                Kind::Text => DataType::Utf8,
+            };
+            Field::new(name, data_type, true)
+        })
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+/// 一个值自己是什么类型；空值不算。
+fn value_kind(value: &Value, is_date: bool) -> Option<Kind> {
+    Some(match value {
+        Value::Null => return None,
+        Value::Bool(_) => Kind::Boolean,
+        Value::Number(n) if n.is_i64() => Kind::Integer,
+        Value::Number(_) => Kind::Float,
+        Value::String(s) if is_date => match parse_date(s) {
+            Some(dt) if dt.time.is_some() => Kind::Timestamp,
+            Some(_) => Kind::Date,
+            None => Kind::Text,
+        },
+        Value::String(_) | Value::Array(_) | Value::Object(_) => Kind::Text,
+    })
+}
+
+/// 同一列里两种类型合起来算什么；整数和小数算小数，日期和时间戳算时间戳，其余对不上的退回文本。
+fn widen(a: Kind, b: Kind) -> Kind {
+    match (a, b) {
+        (a, b) if a == b => a,
+        (Kind::Integer | Kind::Float, Kind::Integer | Kind::Float) => Kind::Float,
+        (Kind::Date | Kind::Timestamp, Kind::Date | Kind::Timestamp) => Kind::Timestamp,
+        _ => Kind::Text,
+    }
+}
+
+/// 一列的值转成 Arrow 数组；`offset` 是这一批之前已经写了多少行，报错时算行号。
+fn array(field: &Field, rows: &[Value], offset: usize) -> Result<ArrayRef> {
+    let values: Vec<&Value> = rows
+        .iter()
+        .map(|row| row.get(field.name()).unwrap_or(&Value::Null))
+        .collect();
+    Ok(match field.data_type() {
+        DataType::Int64 => Arc::new(Int64Array::from(collect(
+            field,
+            &values,
+            offset,
+            &Value::as_i64,
+        )?)),
+        DataType::Float64 => Arc::new(Float64Array::from(collect(
+            field,
+            &values,
+            offset,
+            &Value::as_f64,
+        )?)),
+        DataType::Boolean => Arc::new(BooleanArray::from(collect(
+            field,
+            &values,
+            offset,
+            &Value::as_bool,
+        )?)),
+        DataType::Date32 => Arc::new(Date32Array::from(collect(
+            field,
+            &values,
+            offset,
+            &|value| {
+                let dt = parse_date(value.as_str()?)?;
+                dt.time.is_none().then_some(days(dt.date?))
+            },
+        )?)),
+        DataType::Timestamp(..) => Arc::new(
+            TimestampMicrosecondArray::from(collect(field, &values, offset, &|value| {
+                parse_date(value.as_str()?).map(micros)
+            })?)
+            .with_timezone("UTC"),
+        ),
+        _ => Arc::new(StringArray::from(
+            values
+                .iter()
+                .map(|value| match value {
+                    Value::Null => None,
+                    value => Some(cell(value)),
+                })
+                .collect::<Vec<_>>(),
+        )),
+    })
+}
+
+/// `null` 转成空值，其余交给 `convert`，转不了就报错。
+fn collect<T>(
+    field: &Field,
+    values: &[&Value],
+    offset: usize,
+    convert: &dyn Fn(&Value) -> Option<T>,
+) -> Result<Vec<Option<T>>> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| match value {
+            Value::Null => Ok(None),
+            value => convert(value).map(Some).with_context(|| {
+                format!(
+                    "record {}: {value} in column {:?} is not {}",
+                    offset + i + 1,
+                    field.name(),
+                    field.data_type(),
+                )
+            }),
+        })
+        .collect()
+}
+
+/// 公历日期距 1970-01-01 的天数（Howard Hinnant 的 `days_from_civil`）。
+pub(crate) fn days(date: toml::value::Date) -> i32 {
+    let (month, day) = (i32::from(date.month), i32::from(date.day));
+    let year = i32::from(date.year) - i32::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// 距 Unix 纪元的微秒数；只有日期的算当天零点，没写时区的按 UTC。
+pub(crate) fn micros(dt: toml::value::Datetime) -> i64 {
+    let days = dt.date.map_or(0, days);
+    let (seconds, nanos) = dt.time.map_or((0, 0), |t| {
+        let seconds = i64::from(t.hour) * 3600
+            + i64::from(t.minute) * 60
+            + i64::from(t.second.unwrap_or_default());
+        (seconds, i64::from(t.nanosecond.unwrap_or_default()))
+    });
+    let offset = match dt.offset {
+        Some(toml::value::Offset::Custom { minutes }) => i64::from(minutes) * 60,
+        Some(toml::value::Offset::Z) | None => 0,
+    };
+    (i64::from(days) * 86_400 + seconds - offset) * 1_000_000 + nanos / 1000
+}
+
+/// Parquet / Arrow 文件转成 CSV，`output` 为 `-` 时写标准输出。一批一批地转，不整个读进内存。
+/// 单元格按 Arrow 的显示格式输出，空值写成空单元格。
+///
+/// # Errors
+pub(crate) async fn columnar_to_csv(
+    input: &str,
+    output: &str,
+    format: OutputFormat,
+    delimiter: u8,
+) -> Result<()> {
+    let (input, output) = (input.to_owned(), output.to_owned());
+    task::spawn_blocking(move || {
+        let batches = open(&input, format)?;
+        let sink: Box<dyn Write> = if output == "-" {
+            Box::new(io::stdout())
+        } else {
+            Box::new(File::create(&output)?)
+        };
+        let mut writer = WriterBuilder::new()
+            .delimiter(delimiter)
+            .from_writer(BufWriter::new(sink));
+        writer.write_record(batches.schema().fields().iter().map(|f| f.name()))?;
+
+        let options = FormatOptions::default();
+        for batch in batches {
+            let batch = batch?;
+            let columns = batch
+                .columns()
+                .iter()
+                .map(|column| ArrayFormatter::try_new(column, &options))
+                .collect::<Result<Vec<_>, _>>()?;
+            for row in 0..batch.num_rows() {
+                writer.write_record(columns.iter().map(|c| c.value(row).to_string()))?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    })
+    .await?
+}
+
+type Batches = Box<dyn RecordBatchReader + Send>;
+
+fn open(input: &str, format: OutputFormat) -> Result<Batches> {
+    // 两种格式都要能回头读（Parquet 的元数据在文件尾），标准输入只好整个读进来
+    let stdin = || -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        io::stdin().read_to_end(&mut buf)?;
+        Ok(buf)
+    };
+    let batches: Batches = match format {
+        OutputFormat::Parquet if input == "-" => Box::new(
+            ParquetRecordBatchReaderBuilder::try_new(Bytes::from(stdin()?))?
+                .with_batch_size(BATCH_ROWS)
+                .build()?,
+        ),
+        OutputFormat::Parquet => Box::new(
+            ParquetRecordBatchReaderBuilder::try_new(File::open(input)?)?
+                .with_batch_size(BATCH_ROWS)
+                .build()?,
+        ),
+        OutputFormat::Arrow if input == "-" => ipc(Cursor::new(stdin()?))?,
+        OutputFormat::Arrow => ipc(File::open(input)?)?,
+        format => bail!("{format} is not a columnar format"),
+    };
+    Ok(batches)
+}
+
+/// 文件格式（`.arrow` / `.feather`）和流格式都认。
+fn ipc<R: Read + Seek + Send + 'static>(mut source: R) -> Result<Batches> {
+    let mut magic = [0; ARROW_MAGIC.len()];
+    let is_file = source.read_exact(&mut magic).is_ok() && &magic == ARROW_MAGIC;
+    source.rewind()?;
+    Ok(if is_file {
+        Box::new(FileReader::try_new(source, None).context("invalid arrow file")?)
+    } else {
+        Box::new(StreamReader::try_new(source, None).context("invalid arrow stream")?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_days_and_micros() -> Result<()> {
+        let date = |s: &str| parse_date(s).context("valid date");
+        assert_eq!(date("1970-01-01")?.date.map(days), Some(0));
+        assert_eq!(date("2000-03-01")?.date.map(days), Some(11_017));
+        assert_eq!(date("1969-12-31")?.date.map(days), Some(-1));
+        assert_eq!(micros(date("1970-01-01T01:00:00+01:00")?), 0);
+        assert_eq!(micros(date("1970-01-02T00:00:00.5Z")?), 86_400_500_000);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_columnar_roundtrip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        for format in [OutputFormat::Parquet, OutputFormat::Arrow] {
+            let mut writer = ColumnarWriter::new(format, None, vec!["born".to_owned()]);
+            writer.push(&json!({
+                "name": "Buffon", "kit": 1, "rating": 9.5, "active": true, "born": "1978-01-28",
+            }))?;
+            writer.push(&json!({
+                "name": "Perin", "kit": null, "rating": 7, "active": false, "born": null,
+            }))?;
+            let bytes = writer.finish()?.collect::<Result<Vec<_>>>()?.concat();
+
+            let input = dir.path().join(format!("players.{format}"));
+            let output = dir.path().join("players.csv");
+            std::fs::write(&input, bytes)?;
+            columnar_to_csv(
+                input.to_str().context("utf-8 path")?,
+                output.to_str().context("utf-8 path")?,
+                format,
+                b',',
+            )
+            .await?;
+            assert_eq!(
+                std::fs::read_to_string(&output)?,
+                "name,kit,rating,active,born\nBuffon,1,9.5,true,1978-01-28\nPerin,,7.0,false,\n"
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_columnar_widens_late_mismatch_to_text() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut writer = ColumnarWriter::new(OutputFormat::Parquet, None, Vec::new());
+        // 第一批全是整数，下一批才冒出文本，整列改成 Utf8
+        for kit in 0..BATCH_ROWS {
+            writer.push(&json!({ "kit": kit, "rating": 7 }))?;
+        }
+        writer.push(&json!({ "kit": "abc", "rating": 7.5 }))?;
+        let chunks = writer.finish()?;
+        assert_eq!(
+            chunks
+                .schema
+                .fields()
+                .iter()
+                .map(|f| f.data_type().clone())
+                .collect::<Vec<_>>(),
+            [DataType::Utf8, DataType::Float64]
+        );
+        let bytes = chunks.collect::<Result<Vec<_>>>()?.concat();
+
+        let input = dir.path().join("late.parquet");
+        let output = dir.path().join("late.csv");
+        std::fs::write(&input, bytes)?;
+        columnar_to_csv(
+            input.to_str().context("utf-8 path")?,
+            output.to_str().context("utf-8 path")?,
+            OutputFormat::Parquet,
+            b',',
+        )
+        .await?;
+        let csv = std::fs::read_to_string(&output)?;
+        assert!(csv.starts_with("kit,rating\n0,7.0\n"));
+        assert!(csv.ends_with("\nabc,7.5\n"));
+        Ok(())
+    }
+}
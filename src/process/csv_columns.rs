@@ -0,0 +1,133 @@
+//! `csv --select / --exclude / --rename`：转换前挑列、改名、调整顺序。
+
+use anyhow::{Result, bail};
+use csv::StringRecord;
+use serde_json::Value;
+
+use crate::process::csv_infer::ColumnType;
+
+/// 输出哪些列、按什么顺序、叫什么名字。
+#[derive(Debug, Clone)]
+pub(crate) struct Projection {
+    /// （源列下标，输出列名）
+    columns: Vec<(usize, String)>,
+}
+
+impl Projection {
+    /// 三个选项按固定顺序生效：先 `select`（同时决定输出顺序），再 `exclude`，最后 `rename`。
+    /// 引用了不存在的列一律报错，免得拼错了悄悄不生效。
+    pub(crate) fn new(
+        headers: &[String],
+        select: &[String],
+        exclude: &[String],
+        rename: &[String],
+    ) -> Result<Self> {
+        let mut indices: Vec<usize> = if select.is_empty() {
+            (0..headers.len()).collect()
+        } else {
+            select
+                .iter()
+                .map(|column| resolve(headers, column))
+                .collect::<Result<_>>()?
+        };
+
+        let excluded = exclude
+            .iter()
+            .map(|column| resolve(headers, column))
+            .collect::<Result<Vec<_>>>()?;
+        indices.retain(|i| !excluded.contains(i));
+
+        let mut columns: Vec<(usize, String)> = indices
+            .into_iter()
+            .map(|i| (i, headers[i].clone()))
+            .collect();
+        for pair in rename {
+            let Some((old, new)) = pair.split_once('=') else {
+                bail!("invalid rename {pair:?}, expected old=new");
+            };
+            let i = resolve(headers, old)?;
+            for (_, name) in columns.iter_mut().filter(|(j, _)| *j == i) {
+                new.clone_into(name);
+            }
+        }
+
+        for (n, (_, name)) in columns.iter().enumerate() {
+            if columns[..n].iter().any(|(_, other)| other == name) {
+                bail!("duplicate output column {name:?}");
+            }
+        }
+        Ok(Self { columns })
+    }
+
+    /// 按输出顺序列出（源列下标，输出列名）。
+    pub(crate) fn columns(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.columns.iter().map(|(i, name)| (*i, name.as_str()))
+    }
+
+    /// 按列类型把一条记录转成以输出列名为键的对象；`types` 按源列下标排列。
+    pub(crate) fn row(&self, types: &[ColumnType], record: &StringRecord) -> Value {
+        self.columns
+            .iter()
+            .map(|(i, name)| {
+                let raw = record.get(*i).unwrap_or_default();
+                (name.as_str(), types[*i].parse(raw))
+            })
+            .collect()
+    }
+}
+
+/// 先按表头名找，找不到再当成从 1 开始的序号（和无表头时的 `col1`、`col2` 对应），
+/// 所以表头里恰好有一列叫 `2` 时按名字优先。
+pub(crate) fn resolve(headers: &[String], column: &str) -> Result<usize> {
+    if let Some(i) = headers.iter().position(|h| h == column) {
+        return Ok(i);
+    }
+    match column.parse::<usize>() {
+        Ok(n) if (1..=headers.len()).contains(&n) => Ok(n - 1),
+        Ok(n) => bail!("column index {n} is out of range 1..={}", headers.len()),
+        Err(_) => bail!("column {column:?} is not in the csv header"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|s| (*s).to_owned()).collect()
+    }
+
+    #[test]
+    fn test_projection_select_exclude_rename() -> Result<()> {
+        let headers = strings(&["Name", "Position", "DOB", "Kit Number"]);
+        let record = StringRecord::from(vec!["Buffon", "Goalkeeper", "1978", "77"]);
+        let types = [ColumnType::String; 4];
+
+        let projection = Projection::new(
+            &headers,
+            &strings(&["4", "Name", "DOB"]),
+            &strings(&["DOB"]),
+            &strings(&["4=kit"]),
+        )?;
+        assert_eq!(
+            projection.columns().collect::<Vec<_>>(),
+            [(3, "kit"), (0, "Name")]
+        );
+        assert_eq!(
+            projection.row(&types, &record).to_string(),
+            json!({ "kit": "77", "Name": "Buffon" }).to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_projection_rejects_bad_references() {
+        let headers = strings(&["a", "b"]);
+        assert!(Projection::new(&headers, &strings(&["c"]), &[], &[]).is_err());
+        assert!(Projection::new(&headers, &strings(&["3"]), &[], &[]).is_err());
+        assert!(Projection::new(&headers, &[], &[], &strings(&["a"])).is_err());
+        assert!(Projection::new(&headers, &[], &[], &strings(&["a=b"])).is_err());
+    }
+}
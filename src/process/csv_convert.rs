@@ -1,77 +1,848 @@
-use anyhow::{Context, Result};
-use csv::ReaderBuilder;
-use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    future,
+    io::{BufRead, BufReader, Read},
+    mem,
+    path::{Path, PathBuf},
+    task::Poll,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use csv::StringRecord;
 use serde_json::Value;
-use tokio::fs;
+use tokio::{
+    fs,
+    io::{self, AsyncRead, AsyncWrite, AsyncWriteExt, BufWriter},
+    sync::{mpsc, oneshot},
+    task,
+};
+use tokio_util::io::SyncIoBridge;
+
+use crate::{
+    cli::OutputFormat,
+    get_reader,
+    process::{
+        csv_columnar::ColumnarWriter,
+        csv_columns::Projection,
+        csv_dialect::{CsvReadOptions, SNIFF_BYTES, sniff},
+        csv_filter::Filter,
+        csv_infer::{ColumnType, INFER_SAMPLE_ROWS, apply_schema, infer_columns, parse_date},
+        csv_nested::Nesting,
+        csv_sort::{DEFAULT_SORT_MEMORY, Dedup, SortOrder},
+        csv_xlsx::{XlsxWriter, is_workbook, read_sheet},
+    },
+};
+
+/// 解析线程最多领先下游多少条记录。
+pub(crate) const CHANNEL_CAPACITY: usize = 1024;
+
+/// TOML 顶层必须是表，记录数组挂在这个键下面；输入没有文件名可取时用它。
+const DEFAULT_TOML_ROOT: &str = "records";
 
-use crate::cli::OutputFormat;
+/// [`process_csv`] 写到哪里。
+#[derive(Debug, Clone)]
+pub enum CsvOutput {
+    /// 所有记录写进一个文件
+    File(PathBuf),
+    /// 所有记录写到标准输出，方便接管道
+    Stdout,
+    /// 每条记录单独写成一个文档：`<目录>/<行号>.<格式>`，行号从 1 开始
+    Dir(PathBuf),
+}
 
-#[expect(dead_code)]
-#[derive(Deserialize, Serialize, Debug)]
-#[serde(rename_all = "PascalCase")]
-struct Player {
-    name: String,
-    position: String,
-    #[serde(rename = "DOB")]
-    dob: String,
-    nationality: String,
-    #[serde(rename = "Kit Number")]
-    kit: u8,
+/// [`process_csv`] 的读取与转换选项。
+#[derive(Debug, Clone, Default)]
+pub struct CsvOptions {
+    pub read: CsvReadOptions,
+    /// 按列推断类型，见 `--infer`
+    pub infer: bool,
+    /// 「列名 → 类型」覆盖文件，见 `--schema`
+    pub schema: Option<String>,
+    /// TOML 表数组的键名；不给就取输入文件名去掉扩展名，`juventus.csv` → `juventus`
+    pub toml_root: Option<String>,
+    /// 只输出这些列，并按这个顺序输出；列名或从 1 开始的序号，空表示全部
+    pub select: Vec<String>,
+    /// 不输出这些列
+    pub exclude: Vec<String>,
+    /// `old=new` 改列名，`old` 同样可以是序号
+    pub rename: Vec<String>,
+    /// `--where` 表达式，只输出满足条件的行；列名按原表头写
+    pub filter: Option<String>,
+    /// 按 `address.city`、`tags[0]` 这样的输出列名拼成嵌套对象和数组，见 `--nested`
+    pub nested: bool,
+    /// 排序键，`列` 或 `列:desc`，前面的优先，见 `--sort-by`
+    pub sort_by: Vec<String>,
+    /// 按这些列去重，保留输出顺序里的第一行；`Some` 的空列表表示整行去重
+    pub dedup: Option<Vec<String>>,
+    /// 排序时最多在内存里攒多少字节，超过就写临时文件；`None` 用默认的 256 MiB
+    pub sort_memory: Option<usize>,
 }
 
 /// # Errors
 pub async fn process_csv(
     input: &str,
-    output: String,
+    output: CsvOutput,
     format: OutputFormat,
-    delimiter: char,
-    has_header: bool,
+    opts: &CsvOptions,
 ) -> Result<()> {
-    let delimiter = u8::try_from(delimiter)
-        .ok()
-        .filter(u8::is_ascii)
-        .with_context(|| {
-            format!("delimiter must be a single ASCII character, got {delimiter:?}")
-        })?;
+    let pipeline = Pipeline::open(input, format, opts).await?;
+    let to_stdout = matches!(output, CsvOutput::Stdout);
+    let sink: Box<dyn AsyncWrite + Unpin + Send> = match output {
+        CsvOutput::File(path) => Box::new(fs::File::create(path).await?),
+        CsvOutput::Stdout => Box::new(io::stdout()),
+        CsvOutput::Dir(dir) => return pipeline.write_documents(&dir).await,
+    };
+    match pipeline.write(BufWriter::new(sink)).await {
+        // `rcli csv ... | head` 把管道提前关掉是正常用法，不算出错
+        Err(e) if to_stdout && is_broken_pipe(&e) => Ok(()),
+        written => written.map(drop),
+    }
+}
+
+/// 和 [`process_csv`] 一样转换，结果写进调用方给的 `writer`，写完 flush 之后交还。
+///
+/// # Errors
+pub async fn convert_csv<W: AsyncWrite + Unpin>(
+    input: &str,
+    writer: W,
+    format: OutputFormat,
+    opts: &CsvOptions,
+) -> Result<W> {
+    Pipeline::open(input, format, opts)
+        .await?
+        .write(writer)
+        .await
+}
+
+/// 转换结果整个作为字符串返回；Parquet / Arrow / xlsx 不是文本，用 [`convert_csv`] 写进 `Vec<u8>`。
+///
+/// # Errors
+pub async fn convert_csv_to_string(
+    input: &str,
+    format: OutputFormat,
+    opts: &CsvOptions,
+) -> Result<String> {
+    if format.is_binary() {
+        bail!("{format} output is binary; use convert_csv to write it into bytes");
+    }
+    let bytes = convert_csv(input, Vec::new(), format, opts).await?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+/// 读好表头、按选项准备好过滤、排序、去重、改列这些步骤的一次转换，各种输出共用。
+#[derive(Debug)]
+struct Pipeline {
+    records: CsvRecords,
+    format: OutputFormat,
+    types: Vec<ColumnType>,
+    projection: Projection,
+    filter: Option<Filter>,
+    dedup: Option<Dedup>,
+    nesting: Option<Nesting>,
+    /// 日期列的输出列名
+    dates: Vec<String>,
+    toml_root: String,
+}
+
+impl Pipeline {
+    async fn open(input: &str, format: OutputFormat, opts: &CsvOptions) -> Result<Self> {
+        let mut records = CsvRecords::open(input, &opts.read).await?;
+        let width = records.headers.len();
+
+        // 推断要先看一段样本；样本行留在 CsvRecords 里，之后照样按原顺序输出
+        let sample = records
+            .sample(if opts.infer { INFER_SAMPLE_ROWS } else { 0 })
+            .await?;
+        let mut types = if opts.infer {
+            infer_columns(width, sample)
+        } else {
+            vec![ColumnType::String; width]
+        };
+        if let Some(schema) = &opts.schema {
+            apply_schema(schema, &records.headers, &mut types).await?;
+        }
+        let projection =
+            Projection::new(&records.headers, &opts.select, &opts.exclude, &opts.rename)?;
+        let mut filter = opts
+            .filter
+            .as_deref()
+            .map(|src| Filter::parse(src, &records.headers))
+            .transpose()?;
+        let dedup = opts
+            .dedup
+            .as_deref()
+            .map(|columns| Dedup::new(&records.headers, columns))
+            .transpose()?;
+        if let Some(order) = SortOrder::new(&records.headers, &opts.sort_by)? {
+            // 先过滤再排序，内存和临时文件只花在留下的行上
+            let budget = opts.sort_memory.unwrap_or(DEFAULT_SORT_MEMORY);
+            records = order.sort(records, filter.take(), budget).await?;
+        }
+        let nesting = if opts.nested {
+            if matches!(format, OutputFormat::Csv) || format.is_binary() {
+                bail!("--nested needs a structured output format, not {format}");
+            }
+            Some(Nesting::new(projection.columns().map(|(_, name)| name))?)
+        } else {
+            None
+        };
+        let dates = projection
+            .columns()
+            .filter(|(i, _)| types[*i] == ColumnType::Date)
+            .map(|(_, name)| name.to_owned())
+            .collect();
+        let toml_root = opts
+            .toml_root
+            .clone()
+            .unwrap_or_else(|| default_toml_root(input));
+
+        Ok(Self {
+            records,
+            format,
+            types,
+            projection,
+            filter,
+            dedup,
+            nesting,
+            dates,
+            toml_root,
+        })
+    }
+
+    /// 下一条要输出的记录，已经过滤、去重并转成输出的样子。
+    async fn next(&mut self) -> Option<Result<Value>> {
+        loop {
+            let record = match self.records.next().await? {
+                Ok(record) => record,
+                Err(e) => return Some(Err(e)),
+            };
+            if self.filter.as_ref().is_some_and(|f| !f.matches(&record))
+                || self.dedup.as_mut().is_some_and(|d| !d.first(&record))
+            {
+                continue;
+            }
+            let row = self.projection.row(&self.types, &record);
+            return Some(Ok(match &self.nesting {
+                Some(nesting) => nesting.nest(row),
+                None => row,
+            }));
+        }
+    }
+
+    async fn write<W: AsyncWrite + Unpin>(mut self, writer: W) -> Result<W> {
+        let columns = self
+            .projection
+            .columns()
+            .map(|(_, name)| name.to_owned())
+            .collect();
+        let mut writer = RecordWriter::new(writer, self.format)
+            .with_dates(mem::take(&mut self.dates))
+            .with_toml_root(mem::take(&mut self.toml_root))
+            .with_columns(columns);
+        while let Some(record) = self.next().await {
+            writer.write(&record?).await?;
+        }
+        writer.finish().await
+    }
+
+    /// 每条记录单独写成 `<dir>/<行号>.<格式>`。
+    async fn write_documents(mut self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir).await?;
+        let mut n = 0;
+        while let Some(record) = self.next().await {
+            n += 1;
+            let doc = document(&record?, self.format, &self.dates)?;
+            fs::write(dir.join(format!("{n}.{}", self.format)), doc).await?;
+        }
+        Ok(())
+    }
+}
+
+/// 输入文件名去掉扩展名；读标准输入时没有文件名，用 [`DEFAULT_TOML_ROOT`]。
+fn default_toml_root(input: &str) -> String {
+    Path::new(input)
+        .file_stem()
+        .filter(|_| input != "-")
+        .map_or_else(
+            || DEFAULT_TOML_ROOT.to_owned(),
+            |s| s.to_string_lossy().into_owned(),
+        )
+}
+
+pub(crate) fn is_broken_pipe(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe)
+}
+
+/// 按统一的方言规则读一个 CSV；文件和标准输入（`-`）都走 [`get_reader`]。
+/// 没给的方言项先从开头一段猜出来，见 [`sniff`]；非 UTF-8 的输入在这里转成 UTF-8。
+///
+/// csv crate 只认同步的 `Read`，所以解析放在 `spawn_blocking` 里，解析出的记录经有界
+/// channel 一条条交回来：下游写得慢，channel 一满解析线程就停下等，内存占用不随输入增长。
+///
+/// 没有表头时列名是 `col1`、`col2`……，和有表头时一样能当键用。
+///
+/// 输入是 Excel 工作簿时读其中一个工作表，后面的流程和 CSV 一样，见 [`read_sheet`]。
+#[derive(Debug)]
+pub(crate) struct CsvRecords {
+    pub(crate) headers: Vec<String>,
+    /// 输入本来有没有表头；没有时 `headers` 是生成的
+    pub(crate) has_header: bool,
+    /// [`sample`](Self::sample) 预读的记录，[`next`](Self::next) 先把它们吐完
+    pending: VecDeque<StringRecord>,
+    rx: mpsc::Receiver<csv::Result<StringRecord>>,
+}
+
+impl CsvRecords {
+    pub(crate) async fn open(input: &str, opts: &CsvReadOptions) -> Result<Self> {
+        Self::from_reader(get_reader(input).await?, input, opts).await
+    }
+
+    /// 从任意异步读取端读；`name` 只用在 `--print-dialect` 的输出里。
+    pub(crate) async fn from_reader(
+        reader: impl AsyncRead + Unpin + Send + 'static,
+        name: &str,
+        opts: &CsvReadOptions,
+    ) -> Result<Self> {
+        let source = SyncIoBridge::new(reader);
+        let print_dialect = opts.print_dialect;
+        let opts = opts.clone();
+
+        let (header_tx, header_rx) = oneshot::channel();
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        task::spawn_blocking(move || {
+            let mut source = BufReader::with_capacity(SNIFF_BYTES, source);
+            match source.fill_buf() {
+                Ok(head) if is_workbook(head) => read_workbook(source, &opts, header_tx, &tx),
+                Ok(_) => read_csv(source, &opts, header_tx, &tx),
+                // 接收端不在了说明 open 已经被取消，这个错误没人要
+                Err(e) => drop(header_tx.send(Err(e.into()))),
+            }
+        });
+
+        let opened = header_rx.await.context("csv reader thread exited")??;
+        if print_dialect {
+            eprintln!("{name}: {}", opened.source);
+        }
+        let headers = if opened.has_header {
+            opened.headers.iter().map(str::to_owned).collect()
+        } else {
+            (1..=opened.headers.len())
+                .map(|i| format!("col{i}"))
+                .collect()
+        };
+
+        Ok(Self {
+            headers,
+            has_header: opened.has_header,
+            pending: VecDeque::new(),
+            rx,
+        })
+    }
+
+    /// 预读最多 `n` 条记录供推断之类的用途，读到的记录不会被消耗掉。
+    pub(crate) async fn sample(&mut self, n: usize) -> Result<&[StringRecord]> {
+        while self.pending.len() < n {
+            match self.rx.recv().await {
+                Some(record) => self
+                    .pending
+                    .push_back(record.context("failed to deserialize csv")?),
+                None => break,
+            }
+        }
+        Ok(self.pending.make_contiguous())
+    }
+
+    /// 表头不变，记录改从 `rx` 取；排序之类的步骤把记录重新排好后从这里交回来。
+    pub(crate) fn with_source(self, rx: mpsc::Receiver<csv::Result<StringRecord>>) -> Self {
+        Self {
+            headers: self.headers,
+            has_header: self.has_header,
+            pending: VecDeque::new(),
+            rx,
+        }
+    }
+
+    pub(crate) async fn next(&mut self) -> Option<Result<StringRecord>> {
+        let record = future::poll_fn(|cx| self.poll_next(cx)).await?;
+        Some(record.context("failed to deserialize csv"))
+    }
+
+    /// 不包装错误的 [`next`](Self::next)：调用方要从 `csv::Error` 里取位置。
+    pub(crate) fn poll_next(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<csv::Result<StringRecord>>> {
+        if let Some(record) = self.pending.pop_front() {
+            return Poll::Ready(Some(Ok(record)));
+        }
+        self.rx.poll_recv(cx)
+    }
+}
+
+/// 解析线程读到表头时交回 [`CsvRecords::open`] 的东西。
+struct Opened {
+    /// `--print-dialect` 打出来的：实际用的方言，或者读的是哪个工作表
+    source: String,
+    has_header: bool,
+    /// 没有表头时是第一行，只用来数列
+    headers: StringRecord,
+}
 
-    let mut reader = ReaderBuilder::new()
-        .delimiter(delimiter)
-        .has_headers(has_header)
-        .from_path(input)?;
+type HeaderTx = oneshot::Sender<Result<Opened>>;
+type RecordTx = mpsc::Sender<csv::Result<StringRecord>>;
 
-    let headers: Vec<String> = if has_header {
-        reader.headers()?.iter().map(str::to_owned).collect()
+fn read_csv(source: impl Read, opts: &CsvReadOptions, header_tx: HeaderTx, tx: &RecordTx) {
+    let sniffed = match &opts.sheet {
+        Some(_) => Err(anyhow!("--sheet only applies to xlsx / xls / ods input")),
+        None => sniff(source, opts),
+    };
+    let (dialect, source) = match sniffed {
+        Ok(sniffed) => sniffed,
+        Err(e) => {
+            drop(header_tx.send(Err(e)));
+            return;
+        }
+    };
+    let mut reader = dialect.reader(source);
+    let opened = reader
+        .headers()
+        .cloned()
+        .map(|headers| Opened {
+            source: dialect.to_string(),
+            has_header: dialect.has_header,
+            headers,
+        })
+        .context("failed to read csv header");
+    let ok = opened.is_ok();
+    if header_tx.send(opened).is_err() || !ok {
+        return;
+    }
+    for record in reader.into_records() {
+        // 接收端已经不要了（下游出错提前返回），没必要再读下去
+        if tx.blocking_send(record).is_err() {
+            break;
+        }
+    }
+}
+
+/// 工作簿没法边读边解析，整个读进来取出一个工作表，再一行行交出去。
+fn read_workbook(mut source: impl Read, opts: &CsvReadOptions, header_tx: HeaderTx, tx: &RecordTx) {
+    let mut bytes = Vec::new();
+    let sheet = source
+        .read_to_end(&mut bytes)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| read_sheet(bytes, opts.sheet.as_deref()));
+    let (name, rows) = match sheet {
+        Ok(sheet) => sheet,
+        Err(e) => {
+            drop(header_tx.send(Err(e)));
+            return;
+        }
+    };
+    let has_header = opts.has_header.unwrap_or(true);
+    let mut rows = rows.into_iter();
+    let headers = if has_header {
+        rows.next()
     } else {
-        (1..=reader.headers()?.len())
-            .map(|i| format!("col{i}"))
-            .collect()
+        rows.as_slice().first().cloned()
+    };
+    let opened = Opened {
+        source: format!(
+            "sheet {name:?}, header {}",
+            if has_header { "yes" } else { "no" }
+        ),
+        has_header,
+        headers: headers.unwrap_or_default(),
     };
+    if header_tx.send(Ok(opened)).is_err() {
+        return;
+    }
+    for row in rows {
+        if tx.blocking_send(Ok(row)).is_err() {
+            break;
+        }
+    }
+}
 
-    let records = reader
-        .records()
-        .map(|record| {
-            let record = record?;
-            let json_value = headers
-                .iter()
-                .map(String::as_str)
-                .zip(record.iter())
-                .collect();
-            Ok::<Value, csv::Error>(json_value)
-        })
-        .collect::<Result<Vec<Value>, _>>()
-        .context("failed to deserialize csv")?;
-
-    let json = match format {
-        OutputFormat::Json => serde_json::to_string_pretty(&records)?,
-        OutputFormat::Yaml => serde_saphyr::to_string(&records)?,
-        OutputFormat::Toml => {
-            let mut root = serde_json::Map::new();
-            root.insert("players".into(), Value::Array(records));
-            let value = Value::Object(root);
-            toml::to_string_pretty(&value)?
+/// 逐条写出记录：来一条写一条，内存占用和输入有多大无关。
+///
+/// 三种格式拼出来的结果，和「先收集成 `Vec` 再整体序列化」逐字节相同：
+/// - JSON：`[` `,` `]` 自己写，每条记录 pretty 之后整体缩进两格
+/// - NDJSON：每条记录一行紧凑 JSON，本来就是逐行的格式
+/// - YAML：每条记录序列化成只有一个元素的序列（`- ...`），首尾相接就是整个序列
+/// - TOML：每条记录序列化成一个 `[[<root>]]` 表，空行隔开就是表数组
+/// - CSV：第一条记录之前写表头，之后每条记录一行
+///
+/// Parquet / Arrow 是二进制的列式格式，按批编码，见 [`ColumnarWriter`]；xlsx 见 [`XlsxWriter`]。
+#[derive(Debug)]
+pub(crate) struct RecordWriter<W> {
+    writer: W,
+    format: OutputFormat,
+    count: usize,
+    /// 这些键的值在 TOML 里写成原生日期，其余格式没有日期类型，不受影响。
+    dates: Vec<String>,
+    toml_root: String,
+    /// CSV 的表头；没给就取第一条记录的键，之后的记录按它取值，缺的键留空
+    columns: Option<Vec<String>>,
+    /// Parquet / Arrow 输出时第一条记录到来才建
+    columnar: Option<ColumnarWriter>,
+    /// xlsx 没法边写边出，整个工作表攒在这里，收尾时一次写出
+    xlsx: Option<Box<XlsxWriter>>,
+}
+
+impl<W: AsyncWrite + Unpin> RecordWriter<W> {
+    pub(crate) fn new(writer: W, format: OutputFormat) -> Self {
+        Self {
+            writer,
+            format,
+            count: 0,
+            dates: Vec::new(),
+            toml_root: DEFAULT_TOML_ROOT.to_owned(),
+            columns: None,
+            columnar: None,
+            xlsx: None,
         }
+    }
+
+    pub(crate) fn with_dates(mut self, dates: Vec<String>) -> Self {
+        self.dates = dates;
+        self
+    }
+
+    pub(crate) fn with_toml_root(mut self, toml_root: String) -> Self {
+        self.toml_root = toml_root;
+        self
+    }
+
+    /// 事先定好 CSV 表头，一条记录都没有时也照样写出表头。
+    pub(crate) fn with_columns(mut self, columns: Vec<String>) -> Self {
+        self.columns = Some(columns);
+        self
+    }
+
+    pub(crate) async fn write(&mut self, record: &Value) -> Result<()> {
+        let chunk = match self.format {
+            OutputFormat::Json => {
+                let prefix = if self.count == 0 { "[\n" } else { ",\n" };
+                let body = serde_json::to_string_pretty(record)?;
+                let mut chunk = String::from(prefix);
+                for (i, line) in body.lines().enumerate() {
+                    if i > 0 {
+                        chunk.push('\n');
+                    }
+                    chunk.push_str("  ");
+                    chunk.push_str(line);
+                }
+                chunk
+            }
+            OutputFormat::Ndjson => format!("{}\n", serde_json::to_string(record)?),
+            OutputFormat::Yaml => serde_saphyr::to_string(&[record])?,
+            OutputFormat::Toml => {
+                let prefix = if self.count == 0 { "" } else { "\n" };
+                let table = HashMap::from([(&self.toml_root, [to_toml(record, &self.dates)?])]);
+                format!("{prefix}{}", toml::to_string_pretty(&table)?)
+            }
+            OutputFormat::Csv => {
+                let mut chunk = String::new();
+                if self.count == 0 {
+                    let columns = self.columns.get_or_insert_with(|| keys(record));
+                    chunk.push_str(&csv_line(columns.iter().map(String::as_str))?);
+                }
+                let columns = self.columns.as_deref().unwrap_or_default();
+                chunk.push_str(&csv_line(
+                    columns
+                        .iter()
+                        .map(|c| record.get(c).map(cell).unwrap_or_default()),
+                )?);
+                chunk
+            }
+            OutputFormat::Parquet | OutputFormat::Arrow => {
+                let columnar = self.columnar.get_or_insert_with(|| {
+                    ColumnarWriter::new(self.format, self.columns.clone(), self.dates.clone())
+                });
+                columnar.push(record)?;
+                self.count += 1;
+                return Ok(());
+            }
+            OutputFormat::Xlsx => {
+                self.xlsx
+                    .get_or_insert_with(|| {
+                        Box::new(XlsxWriter::new(self.columns.clone(), self.dates.clone()))
+                    })
+                    .push(record)?;
+                self.count += 1;
+                return Ok(());
+            }
+        };
+        self.writer.write_all(chunk.as_bytes()).await?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// 写收尾并交还底层 writer；一条记录都没有时补上空数组的写法。
+    pub(crate) async fn finish(mut self) -> Result<W> {
+        let tail = match (self.format, self.count) {
+            (OutputFormat::Json, 0) => "[]".to_owned(),
+            (OutputFormat::Json, _) => "\n]".to_owned(),
+            (OutputFormat::Yaml, 0) => serde_saphyr::to_string(&Vec::<Value>::new())?,
+            (OutputFormat::Toml, 0) => {
+                toml::to_string_pretty(&HashMap::from([(&self.toml_root, Vec::<Value>::new())]))?
+            }
+            (OutputFormat::Csv, 0) => match &self.columns {
+                Some(columns) => csv_line(columns.iter().map(String::as_str))?,
+                None => String::new(),
+            },
+            (OutputFormat::Parquet | OutputFormat::Arrow, _) => {
+                let columnar = self.columnar.take().unwrap_or_else(|| {
+                    ColumnarWriter::new(self.format, self.columns.clone(), self.dates.clone())
+                });
+                for bytes in columnar.finish()? {
+                    self.writer.write_all(&bytes?).await?;
+                }
+                String::new()
+            }
+            (OutputFormat::Xlsx, _) => {
+                let xlsx = self.xlsx.take().unwrap_or_else(|| {
+                    Box::new(XlsxWriter::new(self.columns.clone(), self.dates.clone()))
+                });
+                self.writer.write_all(&xlsx.finish()?).await?;
+                String::new()
+            }
+            (
+                OutputFormat::Ndjson | OutputFormat::Yaml | OutputFormat::Toml | OutputFormat::Csv,
+                _,
+            ) => String::new(),
+        };
+        self.writer.write_all(tail.as_bytes()).await?;
+        self.writer.flush().await?;
+        Ok(self.writer)
+    }
+}
+
+/// 单条记录写成一个完整的文档：JSON 对象、YAML 映射、TOML 表，不再包一层数组。
+fn document(record: &Value, format: OutputFormat, dates: &[String]) -> Result<Vec<u8>> {
+    let text = match format {
+        OutputFormat::Json => serde_json::to_string_pretty(record)?,
+        OutputFormat::Ndjson => format!("{}\n", serde_json::to_string(record)?),
+        OutputFormat::Yaml => serde_saphyr::to_string(record)?,
+        OutputFormat::Toml => toml::to_string_pretty(&to_toml(record, dates)?)?,
+        OutputFormat::Csv => {
+            let columns = keys(record);
+            let header = csv_line(columns.iter().map(String::as_str))?;
+            let row = csv_line(
+                columns
+                    .iter()
+                    .map(|c| record.get(c).map(cell).unwrap_or_default()),
+            )?;
+            header + &row
+        }
+        OutputFormat::Parquet | OutputFormat::Arrow => {
+            let mut writer = ColumnarWriter::new(format, None, dates.to_vec());
+            writer.push(record)?;
+            return Ok(writer.finish()?.collect::<Result<Vec<_>>>()?.concat());
+        }
+        OutputFormat::Xlsx => {
+            let mut writer = XlsxWriter::new(None, dates.to_vec());
+            writer.push(record)?;
+            return writer.finish();
+        }
+    };
+    Ok(text.into_bytes())
+}
+
+pub(crate) fn keys(record: &Value) -> Vec<String> {
+    record
+        .as_object()
+        .map(|map| map.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// 按 CSV 的引号规则拼一行，带换行。
+pub(crate) fn csv_line<I>(fields: I) -> Result<String>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(fields)?;
+    let line = writer.into_inner().context("failed to flush csv")?;
+    Ok(String::from_utf8(line)?)
+}
+
+/// 一个 JSON 值写进 CSV 单元格的样子：`null` 为空，字符串原样，其余写成紧凑 JSON。
+pub(crate) fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Bool(_) | Value::Number(_) | Value::Array(_) | Value::Object(_) => value.to_string(),
+    }
+}
+
+/// TOML 没有 null：值为 null 的键直接省掉，数组里的 null 去掉；日期列转成原生日期。
+fn to_toml(record: &Value, dates: &[String]) -> Result<toml::Value> {
+    let Value::Object(map) = record else {
+        return Ok(toml::Value::try_from(record)?);
     };
+    Ok(toml::Value::Table(toml_table(None, map, dates)?))
+}
+
+/// `dates` 里是列名，`--nested` 输出时按 `address.since`、`tags[0]` 这样的路径对上。
+fn toml_table(
+    prefix: Option<&str>,
+    map: &serde_json::Map<String, Value>,
+    dates: &[String],
+) -> Result<toml::Table> {
+    let mut table = toml::Table::new();
+    for (key, value) in map {
+        let path = match prefix {
+            Some(prefix) => Cow::Owned(format!("{prefix}.{key}")),
+            None => Cow::Borrowed(key.as_str()),
+        };
+        if let Some(value) = toml_value(&path, value, dates)? {
+            table.insert(key.clone(), value);
+        }
+    }
+    Ok(table)
+}
+
+fn toml_value(path: &str, value: &Value, dates: &[String]) -> Result<Option<toml::Value>> {
+    Ok(Some(match value {
+        Value::Null => return Ok(None),
+        Value::String(s) if dates.iter().any(|d| d == path) => {
+            parse_date(s).map_or_else(|| toml::Value::String(s.clone()), toml::Value::Datetime)
+        }
+        Value::Object(map) => toml::Value::Table(toml_table(Some(path), map, dates)?),
+        Value::Array(items) => toml::Value::Array(
+            items
+                .iter()
+                .enumerate()
+                .filter_map(|(i, item)| {
+                    toml_value(&format!("{path}[{i}]"), item, dates).transpose()
+                })
+                .collect::<Result<_>>()?,
+        ),
+        value => toml::Value::try_from(value)?,
+    }))
+}
+
+/// csv crate 的分隔符是单个字节，clap 那边收的是 `char`，在这里把关。
+pub(crate) fn ascii_delimiter(delimiter: char) -> Result<u8> {
+    u8::try_from(delimiter)
+        .ok()
+        .filter(u8::is_ascii)
+        .with_context(|| format!("delimiter must be a single ASCII character, got {delimiter:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
 
-    fs::write(output, json).await?;
-    Ok(())
+    use super::*;
+
+    /// 流式写出的结果必须和整体序列化逐字节一致，下游拿到的文件不该因为换了实现而变样。
+    #[tokio::test]
+    async fn test_record_writer_matches_batch_serialization() -> Result<()> {
+        let records = vec![
+            json!({ "Name": "Alice", "Note": "line 1\nline 2", "Kit Number": "1" }),
+            json!({ "Name": "Bob", "Note": "", "Kit Number": "2" }),
+        ];
+
+        for format in [
+            OutputFormat::Json,
+            OutputFormat::Ndjson,
+            OutputFormat::Yaml,
+            OutputFormat::Toml,
+            OutputFormat::Csv,
+        ] {
+            let mut writer = RecordWriter::new(Vec::new(), format);
+            for record in &records {
+                writer.write(record).await?;
+            }
+            let streamed = writer.finish().await?;
+
+            let expected = match format {
+                OutputFormat::Json => serde_json::to_string_pretty(&records)?,
+                OutputFormat::Ndjson => records.iter().map(|r| r.to_string() + "\n").collect(),
+                OutputFormat::Yaml => serde_saphyr::to_string(&records)?,
+                OutputFormat::Toml => {
+                    toml::to_string_pretty(&HashMap::from([(DEFAULT_TOML_ROOT, &records)]))?
+                }
+                OutputFormat::Csv => {
+                    "Name,Note,Kit Number\nAlice,\"line 1\nline 2\",1\nBob,,2\n".to_owned()
+                }
+                OutputFormat::Parquet | OutputFormat::Arrow | OutputFormat::Xlsx => {
+                    unreachable!("not in the list")
+                }
+            };
+            assert_eq!(String::from_utf8(streamed)?, expected, "{format}");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_toml_root_defaults_to_file_stem() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let output = dir.path().join("out.toml");
+        process_csv(
+            "assets/juventus.csv",
+            CsvOutput::File(output.clone()),
+            OutputFormat::Toml,
+            &CsvOptions::default(),
+        )
+        .await?;
+        assert!(
+            fs::read_to_string(&output)
+                .await?
+                .starts_with("[[juventus]]\n")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_out_dir_writes_one_document_per_record() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.csv");
+        fs::write(&input, "id,name\n1,\n2,Bob\n").await?;
+        let opts = CsvOptions {
+            infer: true,
+            ..CsvOptions::default()
+        };
+        process_csv(
+            input.to_str().context("utf-8 path")?,
+            CsvOutput::Dir(dir.path().join("docs")),
+            OutputFormat::Toml,
+            &opts,
+        )
+        .await?;
+
+        // name 列推断成字符串，空串照旧输出；id 是整数
+        let first = fs::read_to_string(dir.path().join("docs/1.toml")).await?;
+        assert_eq!(first, "id = 1\nname = \"\"\n");
+        let second = fs::read_to_string(dir.path().join("docs/2.toml")).await?;
+        assert_eq!(second, "id = 2\nname = \"Bob\"\n");
+        Ok(())
+    }
+
+    /// 推断用的样本不能被吃掉：预读之后 `next()` 仍然从第一条开始。
+    #[tokio::test]
+    async fn test_csv_records_sample_is_not_consumed() -> Result<()> {
+        let mut records =
+            CsvRecords::open("assets/juventus.csv", &CsvReadOptions::default()).await?;
+        let sample = records.sample(2).await?;
+        assert_eq!(sample.len(), 2);
+        assert_eq!(&sample[0][0], "Wojciech Szczesny");
+
+        let first = records.next().await.context("has a first record")??;
+        assert_eq!(&first[0], "Wojciech Szczesny");
+
+        let mut rest = 0;
+        while let Some(record) = records.next().await {
+            record?;
+            rest += 1;
+        }
+        assert_eq!(rest, 26);
+        Ok(())
+    }
 }
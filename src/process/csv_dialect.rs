@@ -0,0 +1,292 @@
+//! 读 CSV 之前先猜方言：编码、分隔符、引号、第一行是不是表头。
+
+use std::{
+    fmt,
+    io::{Cursor, Read},
+    str,
+};
+
+use anyhow::{Context, Result};
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use csv::{ReaderBuilder, StringRecord};
+use encoding_rs::{Encoding, UTF_8};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+
+use crate::process::{
+    csv_convert::ascii_delimiter,
+    csv_infer::{ColumnType, infer_columns},
+};
+
+/// 猜方言时最多看开头多少字节。
+pub(crate) const SNIFF_BYTES: usize = 64 * 1024;
+
+/// 猜表头和分隔符时最多看多少行。
+const SNIFF_ROWS: usize = 100;
+
+const DELIMITERS: [u8; 4] = *b",;\t|";
+
+/// 读 CSV 的方言选项，`None` 的项自动探测。
+#[derive(Debug, Clone, Default)]
+pub struct CsvReadOptions {
+    pub delimiter: Option<char>,
+    pub quote: Option<char>,
+    /// 第一行是不是表头
+    pub has_header: Option<bool>,
+    /// WHATWG 编码名，如 `utf-8`、`latin1`、`windows-1252`、`utf-16le`；文件带 BOM 时以 BOM 为准
+    pub encoding: Option<String>,
+    /// 把实际用的方言打到标准错误，猜错了好对着改参数
+    pub print_dialect: bool,
+    /// 输入是工作簿时读哪个工作表：名字或从 1 开始的序号，默认第一个
+    pub sheet: Option<String>,
+}
+
+/// 实际用来读的方言。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CsvDialect {
+    pub(crate) delimiter: u8,
+    pub(crate) quote: u8,
+    pub(crate) has_header: bool,
+    pub(crate) encoding: &'static Encoding,
+    pub(crate) bom: bool,
+}
+
+impl fmt::Display for CsvDialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "delimiter {:?}, quote {:?}, header {}, encoding {}",
+            char::from(self.delimiter),
+            char::from(self.quote),
+            if self.has_header { "yes" } else { "no" },
+            self.encoding.name(),
+        )?;
+        if self.bom {
+            f.write_str(" (BOM)")?;
+        }
+        Ok(())
+    }
+}
+
+impl CsvDialect {
+    /// 按这个方言读的 csv reader。
+    pub(crate) fn reader<R: Read>(&self, source: R) -> csv::Reader<R> {
+        ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .has_headers(self.has_header)
+            .from_reader(source)
+    }
+}
+
+/// 预读开头 [`SNIFF_BYTES`] 字节猜出方言，命令行上显式给了的项不猜；返回方言和从头开始、已经转成
+/// UTF-8 的输入，BOM 一律去掉。阻塞读，放在解析线程里调。
+pub(crate) fn sniff<R: Read>(
+    mut source: R,
+    opts: &CsvReadOptions,
+) -> Result<(CsvDialect, impl Read + use<R>)> {
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    (&mut source)
+        .take(SNIFF_BYTES as u64)
+        .read_to_end(&mut head)?;
+    let complete = head.len() < SNIFF_BYTES;
+
+    let (encoding, bom) = detect_encoding(&head, complete, opts.encoding.as_deref())?;
+    let (text, _) = encoding.decode_without_bom_handling(&head[bom..]);
+    // 最后一行多半被截断了，不拿来数字段
+    let text = match text.rfind('\n') {
+        Some(end) if !complete => &text[..end],
+        _ => &text[..],
+    };
+
+    let quote = match opts.quote {
+        Some(quote) => u8::try_from(quote)
+            .ok()
+            .filter(u8::is_ascii)
+            .with_context(|| format!("quote must be a single ASCII character, got {quote:?}"))?,
+        None => sniff_quote(text),
+    };
+    let delimiter = match opts.delimiter {
+        Some(delimiter) => ascii_delimiter(delimiter)?,
+        None => sniff_delimiter(text, quote),
+    };
+    let has_header = opts
+        .has_header
+        .unwrap_or_else(|| sniff_header(text, delimiter, quote));
+    let dialect = CsvDialect {
+        delimiter,
+        quote,
+        has_header,
+        encoding,
+        bom: bom > 0,
+    };
+
+    let mut decoder = DecodeReaderBytesBuilder::new();
+    decoder.strip_bom(true);
+    if encoding == UTF_8 {
+        // 本来就是 UTF-8，原样交给 csv crate，不多拷一遍
+        decoder.utf8_passthru(true);
+    } else {
+        decoder.encoding(Some(encoding)).bom_override(true);
+    }
+    Ok((dialect, decoder.build(Cursor::new(head).chain(source))))
+}
+
+/// 有 BOM 就按 BOM（UTF-8 / UTF-16）；否则是合法的 UTF-8 就当 UTF-8；再否则交给 chardetng，
+/// 欧洲那边的 Latin-1 文件一般会猜成 windows-1252（Latin-1 的超集）。返回编码和 BOM 的字节数。
+fn detect_encoding(
+    head: &[u8],
+    complete: bool,
+    label: Option<&str>,
+) -> Result<(&'static Encoding, usize)> {
+    let explicit = label
+        .map(|label| {
+            Encoding::for_label(label.as_bytes())
+                .with_context(|| format!("unknown encoding {label:?}"))
+        })
+        .transpose()?;
+    if let Some(found) = Encoding::for_bom(head) {
+        return Ok(found);
+    }
+    if let Some(encoding) = explicit {
+        return Ok((encoding, 0));
+    }
+    let utf8 = match str::from_utf8(head) {
+        Ok(_) => true,
+        // 只是末尾一个字符被截成了两半
+        Err(e) => !complete && e.error_len().is_none() && e.valid_up_to() + 4 > head.len(),
+    };
+    if utf8 {
+        return Ok((UTF_8, 0));
+    }
+    let mut detector = EncodingDetector::new(Iso2022JpDetection::Deny);
+    detector.feed(head, complete);
+    Ok((detector.guess(None, Utf8Detection::Deny), 0))
+}
+
+/// 字段开头出现 `'` 比 `"` 多才用 `'`。
+fn sniff_quote(text: &str) -> u8 {
+    let at_field_start = |quote: u8| {
+        let bytes = text.as_bytes();
+        (0..bytes.len())
+            .filter(|&i| {
+                bytes[i] == quote
+                    && (i == 0 || bytes[i - 1] == b'\n' || DELIMITERS.contains(&bytes[i - 1]))
+            })
+            .count()
+    };
+    if at_field_start(b'\'') > at_field_start(b'"') {
+        b'\''
+    } else {
+        b'"'
+    }
+}
+
+/// 在 [`DELIMITERS`] 里挑各行字段数最一致、且多于一列的那个，一样一致时挑列多的；都不行（比如只有
+/// 一列）就用 `,`。
+fn sniff_delimiter(text: &str, quote: u8) -> u8 {
+    DELIMITERS
+        .into_iter()
+        .filter_map(|delimiter| {
+            let counts: Vec<usize> = records(text, delimiter, quote)
+                .map(|record| record.len())
+                .collect();
+            let (width, rows) = mode(&counts)?;
+            // 字段数一致的行占多少（千分比），一样时比列数
+            (width > 1).then_some(((rows * 1000 / counts.len(), width), delimiter))
+        })
+        // 分数相同时 max_by_key 取最后一个，倒过来让候选表里靠前的赢
+        .rev()
+        .max_by_key(|(score, _)| *score)
+        .map_or(b',', |(_, delimiter)| delimiter)
+}
+
+/// 拿第一行和下面各行推断出的列类型比，数字 / 布尔 / 日期列上面是个对不上类型的值就算表头，对得上
+/// 就算数据。整张表都是字符串列没法判断，按有表头处理。
+fn sniff_header(text: &str, delimiter: u8, quote: u8) -> bool {
+    let mut rows = records(text, delimiter, quote);
+    let Some(first) = rows.next() else {
+        return true;
+    };
+    let rest: Vec<StringRecord> = rows.collect();
+    if rest.is_empty() {
+        return true;
+    }
+    let types = infer_columns(first.len(), &rest);
+    let votes: i32 = types
+        .iter()
+        .zip(first.iter())
+        .filter(|(t, raw)| !matches!(t, ColumnType::String | ColumnType::Null) && !raw.is_empty())
+        .map(|(t, raw)| if t.accepts(raw) { -1 } else { 1 })
+        .sum();
+    votes >= 0
+}
+
+/// 按给定分隔符和引号解析样本，读到第一处错误为止。
+fn records(text: &str, delimiter: u8, quote: u8) -> impl Iterator<Item = StringRecord> {
+    ReaderBuilder::new()
+        .delimiter(delimiter)
+        .quote(quote)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes())
+        .into_records()
+        .map_while(Result::ok)
+        .take(SNIFF_ROWS)
+}
+
+/// 出现最多的值和它出现的次数，次数一样取较大的值。
+fn mode(counts: &[usize]) -> Option<(usize, usize)> {
+    counts
+        .iter()
+        .map(|&c| (c, counts.iter().filter(|&&x| x == c).count()))
+        .max_by_key(|&(c, n)| (n, c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(bytes: &[u8]) -> Result<(CsvDialect, String)> {
+        let (dialect, mut reader) = sniff(bytes, &CsvReadOptions::default())?;
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        Ok((dialect, text))
+    }
+
+    #[test]
+    fn test_sniff_semicolon_latin1_with_header() -> Result<()> {
+        // Latin-1 没有 BOM；é = 0xE9，ü = 0xFC，小数点是逗号
+        let (dialect, text) =
+            detect(b"Name;Stadt;Gr\xf6\xdfe\nJos\xe9;M\xfcnchen;1,85\nAnna;Z\xfcrich;1,70\n")?;
+        assert_eq!(
+            dialect.to_string(),
+            "delimiter ';', quote '\"', header yes, encoding windows-1252"
+        );
+        assert_eq!(
+            text,
+            "Name;Stadt;Größe\nJosé;München;1,85\nAnna;Zürich;1,70\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sniff_bom_tab_and_headerless() -> Result<()> {
+        let (dialect, text) =
+            detect(b"\xef\xbb\xbfBuffon\t1\t'Juve, Turin'\nPerin\t37\t'Genoa'\n")?;
+        assert_eq!(
+            dialect.to_string(),
+            "delimiter '\\t', quote '\\'', header no, encoding UTF-8 (BOM)"
+        );
+        assert!(text.starts_with("Buffon\t"));
+
+        // 显式给的项照用，不再猜
+        let opts = CsvReadOptions {
+            delimiter: Some(','),
+            has_header: Some(true),
+            ..CsvReadOptions::default()
+        };
+        let (dialect, _) = sniff(&b"a;b\n1;2\n"[..], &opts)?;
+        assert_eq!((dialect.delimiter, dialect.has_header), (b',', true));
+        Ok(())
+    }
+}
@@ -0,0 +1,361 @@
+//! `csv diff`：按键列对比同一份数据的新旧两个版本，列出增加、删除和改动的行。
+
+use std::{collections::HashMap, fmt};
+
+use anyhow::{Context, Result, bail};
+use csv::StringRecord;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    cli::OutputFormat,
+    process::{
+        csv_columns::resolve,
+        csv_convert::{CsvRecords, RecordWriter},
+        csv_dialect::CsvReadOptions,
+    },
+};
+
+/// [`process_csv_diff`] 的选项。
+#[derive(Debug, Clone, Default)]
+pub struct CsvDiffOptions {
+    pub read: CsvReadOptions,
+    /// 键列：列名或从 1 开始的序号，在两个文件里都必须存在
+    pub key: Vec<String>,
+}
+
+/// 对比结果。`changes` 为空就是两个文件内容一致。
+///
+/// 改动和新增的行按新文件的顺序，删除的行排在最后，按旧文件的顺序。
+#[derive(Debug, Serialize)]
+pub struct CsvDiff {
+    pub changes: Vec<Change>,
+    /// 两边都有、内容也一样的行数
+    pub unchanged: usize,
+}
+
+/// 一处差异。结构化输出时每处差异是一条记录，`change` 字段区分种类。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    AddedColumn {
+        column: String,
+    },
+    RemovedColumn {
+        column: String,
+    },
+    /// 只在新文件里有的行，`row` 是整行
+    Added {
+        key: Map<String, Value>,
+        row: Map<String, Value>,
+    },
+    /// 只在旧文件里有的行，`row` 是整行
+    Removed {
+        key: Map<String, Value>,
+        row: Map<String, Value>,
+    },
+    Changed {
+        key: Map<String, Value>,
+        fields: Vec<FieldChange>,
+    },
+}
+
+/// 改动行里变了的一格。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldChange {
+    pub column: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// 旧文件整个读进内存按键建索引，新文件逐行流式读。
+///
+/// 只比较两边都有的列，按列名对齐，列的顺序变了不算改动；新增、删除的列单独列出。
+/// 单元格按原文比较，`1` 和 `1.0` 算不同。
+///
+/// # Errors
+///
+/// 读不了输入、键列不存在或者同一个文件里键重复时返回错误。
+pub async fn process_csv_diff(old: &str, new: &str, opts: &CsvDiffOptions) -> Result<CsvDiff> {
+    if opts.key.is_empty() {
+        bail!("diff needs at least one key column");
+    }
+    let mut olds = CsvRecords::open(old, &opts.read).await?;
+    let mut news = CsvRecords::open(new, &opts.read).await?;
+    let old_keys = key_columns(&olds.headers, &opts.key)?;
+    // 序号只按旧表解析，新表按列名对上，列顺序变了也不会错位
+    let new_keys = old_keys
+        .iter()
+        .map(|&i| {
+            let column = &olds.headers[i];
+            news.headers
+                .iter()
+                .position(|h| h == column)
+                .with_context(|| format!("key column {column:?} is missing from {new}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut changes = Vec::new();
+    for column in news.headers.iter().filter(|h| !olds.headers.contains(h)) {
+        changes.push(Change::AddedColumn {
+            column: column.clone(),
+        });
+    }
+    for column in olds.headers.iter().filter(|h| !news.headers.contains(h)) {
+        changes.push(Change::RemovedColumn {
+            column: column.clone(),
+        });
+    }
+    // 两边都有的列：（旧表下标，新表下标）
+    let shared: Vec<(usize, usize)> = news
+        .headers
+        .iter()
+        .enumerate()
+        .filter_map(|(n, h)| Some((olds.headers.iter().position(|o| o == h)?, n)))
+        .collect();
+
+    // 键 → （数据行号，记录）；取走之后剩下的就是删除的行
+    let mut index: HashMap<Vec<String>, (usize, Option<StringRecord>)> = HashMap::new();
+    let mut row = 0;
+    while let Some(record) = olds.next().await {
+        let record = record?;
+        row += 1;
+        if let Some((first, _)) = index.insert(key(&record, &old_keys), (row, Some(record))) {
+            bail!("{old}: rows {first} and {row} have the same key");
+        }
+    }
+
+    let mut seen = HashMap::new();
+    let mut unchanged = 0;
+    row = 0;
+    while let Some(record) = news.next().await {
+        let record = record?;
+        row += 1;
+        let key = key(&record, &new_keys);
+        if let Some(first) = seen.insert(key.clone(), row) {
+            bail!("{new}: rows {first} and {row} have the same key");
+        }
+        let key_map = labelled(&news.headers, &new_keys, &key);
+        let Some(previous) = index.get_mut(&key).and_then(|(_, r)| r.take()) else {
+            changes.push(Change::Added {
+                key: key_map,
+                row: whole(&news.headers, &record),
+            });
+            continue;
+        };
+        let fields: Vec<FieldChange> = shared
+            .iter()
+            .filter_map(|&(o, n)| {
+                let (before, after) = (previous.get(o)?, record.get(n).unwrap_or_default());
+                (before != after).then(|| FieldChange {
+                    column: news.headers[n].clone(),
+                    old: before.to_owned(),
+                    new: after.to_owned(),
+                })
+            })
+            .collect();
+        if fields.is_empty() {
+            unchanged += 1;
+        } else {
+            changes.push(Change::Changed {
+                key: key_map,
+                fields,
+            });
+        }
+    }
+
+    let mut removed: Vec<(usize, Vec<String>, StringRecord)> = index
+        .into_iter()
+        .filter_map(|(key, (row, record))| Some((row, key, record?)))
+        .collect();
+    removed.sort_unstable_by_key(|(row, ..)| *row);
+    for (_, key, record) in removed {
+        changes.push(Change::Removed {
+            key: labelled(&olds.headers, &old_keys, &key),
+            row: whole(&olds.headers, &record),
+        });
+    }
+    Ok(CsvDiff { changes, unchanged })
+}
+
+impl CsvDiff {
+    /// 每处差异一条记录，用和 `csv` 转换一样的写法输出。
+    ///
+    /// # Errors
+    ///
+    /// 二进制格式不支持。
+    pub async fn render(&self, format: OutputFormat) -> Result<String> {
+        if format.is_binary() {
+            bail!("diffs are returned as text; {format} output is not supported");
+        }
+        let columns = ["change", "column", "key", "row", "fields"].map(str::to_owned);
+        let mut writer = RecordWriter::new(Vec::new(), format)
+            .with_toml_root("changes".to_owned())
+            .with_columns(columns.to_vec());
+        for change in &self.changes {
+            writer.write(&serde_json::to_value(change)?).await?;
+        }
+        Ok(String::from_utf8(writer.finish().await?)?)
+    }
+
+    /// （新增行数，删除行数，改动行数）
+    pub fn counts(&self) -> (usize, usize, usize) {
+        self.changes
+            .iter()
+            .fold((0, 0, 0), |(a, r, c), change| match change {
+                Change::Added { .. } => (a + 1, r, c),
+                Change::Removed { .. } => (a, r + 1, c),
+                Change::Changed { .. } => (a, r, c + 1),
+                Change::AddedColumn { .. } | Change::RemovedColumn { .. } => (a, r, c),
+            })
+    }
+}
+
+/// 给人看的报告：`+` 新增、`-` 删除、`~` 改动，最后一行是汇总。
+impl fmt::Display for CsvDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            match change {
+                Change::AddedColumn { column } => writeln!(f, "+ column {column:?}")?,
+                Change::RemovedColumn { column } => writeln!(f, "- column {column:?}")?,
+                Change::Added { key, .. } => writeln!(f, "+ {}", Key(key))?,
+                Change::Removed { key, .. } => writeln!(f, "- {}", Key(key))?,
+                Change::Changed { key, fields } => {
+                    writeln!(f, "~ {}", Key(key))?;
+                    for field in fields {
+                        writeln!(
+                            f,
+                            "    {}: {:?} -> {:?}",
+                            field.column, field.old, field.new
+                        )?;
+                    }
+                }
+            }
+        }
+        let (added, removed, changed) = self.counts();
+        writeln!(
+            f,
+            "{added} added, {removed} removed, {changed} changed, {} unchanged",
+            self.unchanged
+        )
+    }
+}
+
+/// 键写成 `Name=Buffon, Season=2018`。
+struct Key<'a>(&'a Map<String, Value>);
+
+impl fmt::Display for Key<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(
+                f,
+                "{name}={}",
+                value.as_str().unwrap_or_default().escape_debug()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn key_columns(headers: &[String], key: &[String]) -> Result<Vec<usize>> {
+    key.iter().map(|column| resolve(headers, column)).collect()
+}
+
+fn key(record: &StringRecord, columns: &[usize]) -> Vec<String> {
+    columns
+        .iter()
+        .map(|&i| record.get(i).unwrap_or_default().to_owned())
+        .collect()
+}
+
+fn labelled(headers: &[String], columns: &[usize], key: &[String]) -> Map<String, Value> {
+    columns
+        .iter()
+        .zip(key)
+        .map(|(&i, value)| (headers[i].clone(), Value::String(value.clone())))
+        .collect()
+}
+
+fn whole(headers: &[String], record: &StringRecord) -> Map<String, Value> {
+    headers
+        .iter()
+        .zip(record)
+        .map(|(name, value)| (name.clone(), Value::String(value.to_owned())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_diff_by_key() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let old = dir.path().join("old.csv");
+        let new = dir.path().join("new.csv");
+        std::fs::write(
+            &old,
+            "name,kit,pos\nBuffon,1,GK\nPerin,37,GK\nChiellini,3,DF\n",
+        )?;
+        std::fs::write(
+            &new,
+            "name,pos,kit,agent\nPerin,GK,37,x\nBuffon,GK,77,y\nKean,FW,18,z\n",
+        )?;
+        let opts = CsvDiffOptions {
+            key: vec!["name".to_owned()],
+            ..CsvDiffOptions::default()
+        };
+        let diff = process_csv_diff(
+            old.to_str().context("utf-8 path")?,
+            new.to_str().context("utf-8 path")?,
+            &opts,
+        )
+        .await?;
+
+        // 列顺序变了不算改动，Perin 一行没变
+        assert_eq!(diff.counts(), (1, 1, 1));
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(
+            diff.to_string(),
+            concat!(
+                "+ column \"agent\"\n",
+                "~ name=Buffon\n",
+                "    kit: \"1\" -> \"77\"\n",
+                "+ name=Kean\n",
+                "- name=Chiellini\n",
+                "1 added, 1 removed, 1 changed, 1 unchanged\n",
+            )
+        );
+
+        std::fs::write(&new, "name,kit\nBuffon,1\nBuffon,77\n")?;
+        let dup = process_csv_diff(
+            old.to_str().context("utf-8 path")?,
+            new.to_str().context("utf-8 path")?,
+            &opts,
+        )
+        .await;
+        assert!(dup.is_err_and(|e| e.to_string().contains("rows 1 and 2")));
+
+        // 序号键按旧表解析，新表按列名对上
+        std::fs::write(&old, "name,kit\nBuffon,1\nPerin,37\n")?;
+        std::fs::write(&new, "kit,name\n37,Perin\n1,Buffon\n")?;
+        let opts = CsvDiffOptions {
+            key: vec!["1".to_owned()],
+            ..CsvDiffOptions::default()
+        };
+        let old = old.to_str().context("utf-8 path")?;
+        let new_path = new.to_str().context("utf-8 path")?;
+        let same = process_csv_diff(old, new_path, &opts).await?;
+        assert_eq!((same.counts(), same.unchanged), ((0, 0, 0), 2));
+
+        std::fs::write(&new, "kit\n37\n1\n")?;
+        let missing = process_csv_diff(old, new_path, &opts).await;
+        assert!(
+            missing.is_err_and(|e| e.to_string().starts_with("key column \"name\" is missing"))
+        );
+        Ok(())
+    }
+}
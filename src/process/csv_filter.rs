@@ -0,0 +1,417 @@
+//! `csv --where`：只转换满足条件的行，如 `Position == "Goalkeeper" && Kit Number < 30`。
+
+use std::{cmp::Ordering, ops::Range};
+
+use anyhow::{Result, anyhow};
+use csv::StringRecord;
+use regex::Regex;
+use unicode_width::UnicodeWidthStr;
+
+/// 解析好的 `--where` 表达式。
+#[derive(Debug)]
+pub(crate) struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    /// 拿到表头后整体解析一遍，列名拼错、语法错误都在转换开始前报出来，并用 `^` 标出位置。
+    ///
+    /// - 比较：`==` `!=` `<` `<=` `>` `>=`，见 [`compare`]
+    /// - 字符串：`contains` 子串匹配，`matches`（或 `=~`）正则匹配，正则必须写成字符串字面量
+    /// - 组合：`&&` / `and`、`||` / `or`、`!` / `not`、括号；优先级 `not` > `and` > `or`
+    pub(crate) fn parse(src: &str, headers: &[String]) -> Result<Self> {
+        let tokens = lex(src)?;
+        let mut parser = Parser {
+            src,
+            headers,
+            tokens,
+            pos: 0,
+        };
+        let expr = parser.or()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(error(
+                src,
+                &token.span,
+                "expected `&&`, `||` or end of expression",
+            ));
+        }
+        Ok(Self { expr })
+    }
+
+    pub(crate) fn matches(&self, record: &StringRecord) -> bool {
+        self.expr.eval(record)
+    }
+}
+
+#[derive(Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, CmpOp, Operand),
+    Contains(Operand, Operand),
+    Matches(Operand, Regex),
+}
+
+#[derive(Debug)]
+enum Operand {
+    /// 源列下标
+    Column(usize),
+    Literal(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Expr {
+    fn eval(&self, record: &StringRecord) -> bool {
+        match self {
+            Self::And(a, b) => a.eval(record) && b.eval(record),
+            Self::Or(a, b) => a.eval(record) || b.eval(record),
+            Self::Not(a) => !a.eval(record),
+            Self::Compare(a, op, b) => {
+                let ordering = compare(a.value(record), b.value(record));
+                match op {
+                    CmpOp::Eq => ordering == Ordering::Equal,
+                    CmpOp::Ne => ordering != Ordering::Equal,
+                    CmpOp::Lt => ordering == Ordering::Less,
+                    CmpOp::Le => ordering != Ordering::Greater,
+                    CmpOp::Gt => ordering == Ordering::Greater,
+                    CmpOp::Ge => ordering != Ordering::Less,
+                }
+            }
+            Self::Contains(a, b) => a.value(record).contains(b.value(record)),
+            Self::Matches(a, re) => re.is_match(a.value(record)),
+        }
+    }
+}
+
+impl Operand {
+    fn value<'a>(&'a self, record: &'a StringRecord) -> &'a str {
+        match self {
+            Self::Column(i) => record.get(*i).unwrap_or_default(),
+            Self::Literal(s) => s,
+        }
+    }
+}
+
+/// 两边都是有限的数字按数值比，`30` 和 `30.0` 相等；否则按字符串字典序比，
+/// 和 `csv sort` 一样，`NaN`、`inf` 这类文本不当数字。
+fn compare(a: &str, b: &str) -> Ordering {
+    let number = |s: &str| s.trim().parse::<f64>().ok().filter(|n| n.is_finite());
+    match (number(a), number(b)) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    /// 裸词；相邻的裸词在解析时拼成带空格的列名
+    Word,
+    /// 反引号括起来的列名
+    Column(String),
+    Str(String),
+    Cmp(CmpOp),
+    Contains,
+    Matches,
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+#[derive(Debug)]
+struct Token {
+    tok: Tok,
+    /// 在表达式里的字节范围，报错时用来画 `^`
+    span: Range<usize>,
+}
+
+/// 列名直接写，中间可以有空格（`Kit Number`），撞上关键字或含运算符时用反引号括起来；字符串用双引号
+/// 或单引号，引号内用 `\` 转义。不是列名的裸词如果是数字，就当数字字面量。
+fn lex(src: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = src.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut two = |second: char, tok: Tok| chars.next_if(|(_, c)| *c == second).map(|_| tok);
+        let tok = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Tok::LParen,
+            ')' => Tok::RParen,
+            '=' => match two('=', Tok::Cmp(CmpOp::Eq)).or_else(|| two('~', Tok::Matches)) {
+                Some(tok) => tok,
+                None => return Err(error(src, &(start..start + 1), "unexpected `=`, use `==`")),
+            },
+            '!' => two('=', Tok::Cmp(CmpOp::Ne)).unwrap_or(Tok::Not),
+            '<' => two('=', Tok::Cmp(CmpOp::Le)).unwrap_or(Tok::Cmp(CmpOp::Lt)),
+            '>' => two('=', Tok::Cmp(CmpOp::Ge)).unwrap_or(Tok::Cmp(CmpOp::Gt)),
+            '&' => two('&', Tok::And)
+                .ok_or_else(|| error(src, &(start..start + 1), "unexpected `&`, use `&&`"))?,
+            '|' => two('|', Tok::Or)
+                .ok_or_else(|| error(src, &(start..start + 1), "unexpected `|`, use `||`"))?,
+            '"' | '\'' | '`' => {
+                let mut text = String::new();
+                let mut closed = false;
+                while let Some((_, next)) = chars.next() {
+                    match next {
+                        // 只转义引号和反斜杠本身，`\d` 之类原样交给正则
+                        '\\' => match chars.next_if(|(_, n)| *n == c || *n == '\\') {
+                            Some((_, escaped)) => text.push(escaped),
+                            None => text.push('\\'),
+                        },
+                        next if next == c => {
+                            closed = true;
+                            break;
+                        }
+                        next => text.push(next),
+                    }
+                }
+                if !closed {
+                    return Err(error(src, &(start..src.len()), "unterminated quote"));
+                }
+                if c == '`' {
+                    Tok::Column(text)
+                } else {
+                    Tok::Str(text)
+                }
+            }
+            _ => {
+                while chars
+                    .next_if(|(_, c)| !c.is_whitespace() && !"()=!<>&|\"'`".contains(*c))
+                    .is_some()
+                {}
+                let end = chars.peek().map_or(src.len(), |(i, _)| *i);
+                match src[start..end].to_lowercase().as_str() {
+                    "and" => Tok::And,
+                    "or" => Tok::Or,
+                    "not" => Tok::Not,
+                    "contains" => Tok::Contains,
+                    "matches" => Tok::Matches,
+                    _ => Tok::Word,
+                }
+            }
+        };
+        let end = chars.peek().map_or(src.len(), |(i, _)| *i);
+        tokens.push(Token {
+            tok,
+            span: start..end,
+        });
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    headers: &'a [String],
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos).map(|t| &t.tok)
+    }
+
+    /// 当前位置的范围；到了末尾就指向表达式最后面。
+    fn span(&self) -> Range<usize> {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.src.len()..self.src.len(), |t| t.span.clone())
+    }
+
+    fn eat(&mut self, tok: &Tok) -> bool {
+        let matched = self.peek() == Some(tok);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.eat(&Tok::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while self.eat(&Tok::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat(&Tok::Not) {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat(&Tok::LParen) {
+            let open = self.tokens[self.pos - 1].span.clone();
+            let expr = self.or()?;
+            if !self.eat(&Tok::RParen) {
+                return Err(error(self.src, &open, "unclosed `(`"));
+            }
+            return Ok(expr);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let left = self.operand()?;
+        let op_span = self.span();
+        let op = self.peek().cloned();
+        self.pos += usize::from(op.is_some());
+        match op {
+            Some(Tok::Cmp(op)) => Ok(Expr::Compare(left, op, self.operand()?)),
+            Some(Tok::Contains) => Ok(Expr::Contains(left, self.operand()?)),
+            Some(Tok::Matches) => {
+                let span = self.span();
+                let Some(Tok::Str(pattern)) = self.peek().cloned() else {
+                    return Err(error(
+                        self.src,
+                        &span,
+                        "expected a quoted regex after `matches`",
+                    ));
+                };
+                self.pos += 1;
+                let re =
+                    Regex::new(&pattern).map_err(|e| error(self.src, &span, &e.to_string()))?;
+                Ok(Expr::Matches(left, re))
+            }
+            _ => Err(error(
+                self.src,
+                &op_span,
+                "expected a comparison: `==` `!=` `<` `<=` `>` `>=` `contains` `matches`",
+            )),
+        }
+    }
+
+    /// 一个值：字符串字面量、反引号列名，或者若干相邻裸词拼成的列名 / 数字。
+    fn operand(&mut self) -> Result<Operand> {
+        let start = self.span();
+        match self.peek().cloned() {
+            Some(Tok::Str(s)) => {
+                self.pos += 1;
+                Ok(Operand::Literal(s))
+            }
+            Some(Tok::Column(name)) => {
+                self.pos += 1;
+                self.column(&name, &start)
+            }
+            Some(Tok::Word) => {
+                let mut end = start.end;
+                while self.peek() == Some(&Tok::Word) {
+                    end = self.tokens[self.pos].span.end;
+                    self.pos += 1;
+                }
+                let span = start.start..end;
+                let word = &self.src[span.clone()];
+                if !self.headers.iter().any(|h| h == word) && word.parse::<f64>().is_ok() {
+                    return Ok(Operand::Literal(word.to_owned()));
+                }
+                self.column(word, &span)
+            }
+            _ => Err(error(self.src, &start, "expected a column name or a value")),
+        }
+    }
+
+    fn column(&self, name: &str, span: &Range<usize>) -> Result<Operand> {
+        self.headers
+            .iter()
+            .position(|h| h == name)
+            .map(Operand::Column)
+            .ok_or_else(|| {
+                error(
+                    self.src,
+                    span,
+                    &format!("column {name:?} is not in the csv header"),
+                )
+            })
+    }
+}
+
+/// 报错时把表达式原样打出来，在出错的位置下面画 `^`。
+fn error(src: &str, span: &Range<usize>, msg: &str) -> anyhow::Error {
+    let indent = src[..span.start].width();
+    let marks = src[span.clone()].width().max(1);
+    anyhow!(
+        "invalid --where: {msg}\n  {src}\n  {}{}",
+        " ".repeat(indent),
+        "^".repeat(marks)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers() -> Vec<String> {
+        ["Name", "Position", "Kit Number", "Nationality"]
+            .map(str::to_owned)
+            .to_vec()
+    }
+
+    fn matches(src: &str, row: &[&str]) -> Result<bool> {
+        Ok(Filter::parse(src, &headers())?.matches(&StringRecord::from(row.to_vec())))
+    }
+
+    #[test]
+    fn test_filter_matches() -> Result<()> {
+        let buffon = ["Gianluigi Buffon", "Goalkeeper", "77", "Italy"];
+        let perin = ["Mattia Perin", "Goalkeeper", "37", "Italy"];
+        let src = r#"Position == "Goalkeeper" && Kit Number < 40"#;
+        assert!(!matches(src, &buffon)?);
+        assert!(matches(src, &perin)?);
+
+        // 数字按数值比：9 < 10，字符串比的话正好反过来
+        assert!(matches("Kit Number > 9", &buffon)?);
+        assert!(matches("`Kit Number` == 77.0", &buffon)?);
+        // NaN、inf 是文本，按字符串比
+        assert!(!matches("Nationality == 'nan'", &["", "", "", "NaN"])?);
+        assert!(matches("Nationality > 'Inf'", &["", "", "", "inf"])?);
+        assert!(matches(
+            r#"not (Name contains "Perin") and Name =~ '^G\w+ B'"#,
+            &buffon
+        )?);
+        assert!(matches(
+            r#"Nationality != 'Italy' || Kit Number >= 77 AND Name matches "Buffon$""#,
+            &buffon
+        )?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_errors_point_at_token() {
+        let err = |src: &str| {
+            Filter::parse(src, &headers())
+                .err()
+                .map(|e| e.to_string())
+                .unwrap_or_default()
+        };
+
+        assert_eq!(
+            err("Kit Nmber < 30"),
+            "invalid --where: column \"Kit Nmber\" is not in the csv header\n  Kit Nmber < 30\n  \
+             ^^^^^^^^^"
+        );
+        assert_eq!(
+            err(r#"Position = "Goalkeeper""#),
+            "invalid --where: unexpected `=`, use `==`\n  Position = \"Goalkeeper\"\n           ^"
+        );
+        assert!(err("Name matches \"(\"").contains("^^^"));
+        assert!(err("(Name == 'a'").contains("unclosed `(`"));
+        assert!(err("Name == 'a' Position").contains("expected `&&`, `||`"));
+        assert!(err("Name").contains("expected a comparison"));
+    }
+}
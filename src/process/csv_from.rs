@@ -0,0 +1,313 @@
+//! `csv from`：把 JSON / NDJSON / YAML / TOML / Parquet / Arrow 里的一组记录还原成 CSV，是
+//! [`process_csv`](super::process_csv) 的反方向。
+
+use std::collections::HashSet;
+
+use anyhow::{Context, Result, bail};
+use csv::WriterBuilder;
+use serde_json::{Map, Value};
+use tokio::{
+    fs,
+    io::{self, AsyncReadExt, AsyncWriteExt},
+};
+
+use crate::{
+    cli::OutputFormat,
+    get_reader,
+    process::{
+        csv_columnar::columnar_to_csv,
+        csv_convert::{ascii_delimiter, cell},
+    },
+};
+
+/// 表头取所有记录键的并集，按首次出现的顺序排列，某条记录缺的列留空。`nested` 时后面记录才出现的
+/// 列（比如更长的数组）插在同一个顶层键的列后面，不落到表尾。
+///
+/// # Errors
+pub async fn process_csv_from(
+    input: &str,
+    output: String,
+    format: OutputFormat,
+    delimiter: char,
+    nested: bool,
+) -> Result<()> {
+    let delimiter = ascii_delimiter(delimiter)?;
+    if matches!(format, OutputFormat::Xlsx) {
+        bail!("read workbooks with `rcli csv -i <book.xlsx> --format csv` instead");
+    }
+    if format.is_columnar() {
+        if nested {
+            bail!("--nested does not apply to {format} input");
+        }
+        return columnar_to_csv(input, &output, format, delimiter).await;
+    }
+
+    let mut reader = get_reader(input).await?;
+    let mut content = String::new();
+    reader.read_to_string(&mut content).await?;
+
+    let value: Value = match format {
+        OutputFormat::Json => serde_json::from_str(&content)?,
+        OutputFormat::Ndjson => content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str::<Value>)
+            .collect::<Result<Value, _>>()?,
+        OutputFormat::Yaml => serde_saphyr::from_str(&content)?,
+        OutputFormat::Toml => toml_to_json(toml::from_str(&content)?),
+        OutputFormat::Csv => bail!("input is already csv"),
+        OutputFormat::Parquet | OutputFormat::Arrow | OutputFormat::Xlsx => {
+            unreachable!("handled above")
+        }
+    };
+
+    let rows = extract_records(value)?
+        .into_iter()
+        .enumerate()
+        .map(|(i, record)| match record {
+            Value::Object(map) => {
+                let mut row = Map::new();
+                flatten(None, map, nested, &mut row);
+                Ok(row)
+            }
+            other => bail!(
+                "record #{} is not an object: {other}; every record must be an object with column \
+                 names as keys",
+                i + 1
+            ),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut headers: Vec<&str> = Vec::new();
+    let mut seen: HashSet<&str> = HashSet::new();
+    for key in rows.iter().flat_map(Map::keys) {
+        if !seen.insert(key) {
+            continue;
+        }
+        let sibling = nested
+            .then(|| headers.iter().rposition(|h| root_key(h) == root_key(key)))
+            .flatten();
+        match sibling {
+            Some(i) => headers.insert(i + 1, key),
+            None => headers.push(key),
+        }
+    }
+
+    let mut writer = WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(Vec::new());
+    writer.write_record(&headers)?;
+    for row in &rows {
+        writer.write_record(
+            headers
+                .iter()
+                .map(|h| row.get(*h).map(cell).unwrap_or_default()),
+        )?;
+    }
+    let csv = writer.into_inner().context("failed to flush csv")?;
+
+    if output == "-" {
+        let mut stdout = io::stdout();
+        stdout.write_all(&csv).await?;
+        stdout.flush().await?;
+    } else {
+        fs::write(output, csv).await?;
+    }
+    Ok(())
+}
+
+/// 顶层是数组时每个元素是一条记录（NDJSON 读进来也是数组）；只有一个键、值是数组的对象取这个数组，
+/// `[[juventus]]` 这样的 TOML 和 `{"data": [...]}` 的接口返回都走这一条，数组里全是标量时成了
+/// 以这个键为列名的单列表；其余的对象整个算一条记录。
+fn extract_records(value: Value) -> Result<Vec<Value>> {
+    match value {
+        Value::Array(records) => Ok(records),
+        Value::Object(mut map) => {
+            if map.len() == 1
+                && let Some((key, Value::Array(records))) = map.iter_mut().next()
+            {
+                let records = std::mem::take(records);
+                if records.iter().any(Value::is_object) {
+                    return Ok(records);
+                }
+                return Ok(records
+                    .into_iter()
+                    .map(|value| Value::Object(Map::from_iter([(key.clone(), value)])))
+                    .collect());
+            }
+            Ok(vec![Value::Object(map)])
+        }
+        other => bail!("expected an array of records or an object, got: {other}"),
+    }
+}
+
+/// TOML 的日期时间写回它的字面量（`2024-01-31`），不然会变成 `{"$__toml_private_datetime": ...}`。
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(n) => Value::from(n),
+        toml::Value::Float(f) => serde_json::Number::from_f64(f)
+            .map_or_else(|| Value::String(f.to_string()), Value::Number),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(dt) => Value::String(dt.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// 嵌套对象递归展开成点号连接的列名（`address.city`）；叶子原样放进 `out`，写 CSV 时再转成文本：
+/// 数组和空对象写成紧凑 JSON，`null` 写成空单元格。
+fn flatten(
+    prefix: Option<&str>,
+    map: Map<String, Value>,
+    nested: bool,
+    out: &mut Map<String, Value>,
+) {
+    for (key, value) in map {
+        let key = match prefix {
+            Some(prefix) => format!("{prefix}.{key}"),
+            None => key,
+        };
+        flatten_value(key, value, nested, out);
+    }
+}
+
+/// `nested` 时非空数组也展开成 `tags[0]`、`tags[1]`，和 `csv --nested` 互为逆操作。
+fn flatten_value(key: String, value: Value, nested: bool, out: &mut Map<String, Value>) {
+    match value {
+        Value::Object(inner) if !inner.is_empty() => flatten(Some(&key), inner, nested, out),
+        Value::Array(items) if nested && !items.is_empty() => {
+            for (i, item) in items.into_iter().enumerate() {
+                flatten_value(format!("{key}[{i}]"), item, nested, out);
+            }
+        }
+        leaf => {
+            out.insert(key, leaf);
+        }
+    }
+}
+
+/// `address.city`、`tags[0]` 的顶层键。
+fn root_key(column: &str) -> &str {
+    column.split(['.', '[']).next().unwrap_or(column)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_flatten_nested_objects() {
+        let Value::Object(record) = json!({
+            "name": "Alice",
+            "address": { "city": "Turin", "geo": { "lat": 45.07 } },
+            "tags": ["a", "b"],
+            "meta": {},
+            "active": true,
+            "note": null,
+        }) else {
+            unreachable!()
+        };
+
+        let mut row = Map::new();
+        flatten(None, record, false, &mut row);
+
+        let cells: Vec<(&str, String)> = row.iter().map(|(k, v)| (k.as_str(), cell(v))).collect();
+        assert_eq!(
+            cells,
+            [
+                ("name", "Alice".to_owned()),
+                ("address.city", "Turin".to_owned()),
+                ("address.geo.lat", "45.07".to_owned()),
+                ("tags", r#"["a","b"]"#.to_owned()),
+                ("meta", "{}".to_owned()),
+                ("active", "true".to_owned()),
+                ("note", String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_records_unwraps_single_array_key() -> Result<()> {
+        let records = extract_records(json!({ "players": [{ "a": 1 }, { "a": 2 }] }))?;
+        assert_eq!(records.len(), 2);
+
+        let records = extract_records(json!({ "a": 1, "b": [1, 2] }))?;
+        assert_eq!(records, [json!({ "a": 1, "b": [1, 2] })]);
+
+        // 标量数组成了单列表
+        let records = extract_records(json!({ "tags": ["a", 2, null] }))?;
+        assert_eq!(
+            records,
+            [
+                json!({ "tags": "a" }),
+                json!({ "tags": 2 }),
+                json!({ "tags": null })
+            ]
+        );
+
+        assert!(extract_records(json!("scalar")).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_process_csv_from_unions_headers() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.json");
+        let output = dir.path().join("out.csv");
+        fs::write(
+            &input,
+            r#"[{"b": 1, "a": "x"}, {"c": true, "a": "y,z"}, {"b": 2}]"#,
+        )
+        .await?;
+
+        process_csv_from(
+            input.to_str().context("utf-8 path")?,
+            output.to_string_lossy().into_owned(),
+            OutputFormat::Json,
+            ',',
+            false,
+        )
+        .await?;
+
+        let csv = fs::read_to_string(&output).await?;
+        assert_eq!(csv, "b,a,c\n1,x,\n,\"y,z\",true\n2,,\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_process_csv_from_nested_expands_arrays() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.json");
+        let output = dir.path().join("out.csv");
+        fs::write(
+            &input,
+            r#"[{"name": "a", "tags": ["x"], "ids": []},
+                {"name": "b", "tags": ["y", "z"], "addr": {"city": "T"}}]"#,
+        )
+        .await?;
+
+        process_csv_from(
+            input.to_str().context("utf-8 path")?,
+            output.to_string_lossy().into_owned(),
+            OutputFormat::Json,
+            ',',
+            true,
+        )
+        .await?;
+
+        let csv = fs::read_to_string(&output).await?;
+        assert_eq!(
+            csv,
+            "name,tags[0],tags[1],ids,addr.city\na,x,,[],\nb,y,z,,T\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+//! `csv --infer`：按列推断类型，输出 JSON / YAML / TOML 的原生类型而不是一律字符串。
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::{Number, Value};
+use tokio::fs;
+
+use crate::cli::OutputFormat;
+
+/// 推断列类型时最多看多少行。看完就开始流式输出，不为了推断把整个文件读进内存。
+pub(crate) const INFER_SAMPLE_ROWS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ColumnType {
+    #[serde(alias = "str")]
+    String,
+    #[serde(alias = "int")]
+    Integer,
+    Float,
+    #[serde(alias = "bool")]
+    Boolean,
+    /// ISO 8601 日期或日期时间：`2024-01-31`、`2024-01-31T08:00:00Z`。
+    /// JSON / YAML 没有日期类型，照旧输出字符串；TOML 输出原生日期。
+    Date,
+    /// 样本里整列都是空的。
+    Null,
+}
+
+impl ColumnType {
+    /// 单个非空值最窄能归到哪一类。
+    fn of(raw: &str) -> Self {
+        if is_integer(raw) {
+            // 超出 i64 的长串数字多半是编号，转成浮点数会丢精度
+            if raw.parse::<i64>().is_ok() {
+                Self::Integer
+            } else {
+                Self::String
+            }
+        } else if parse_float(raw).is_some() {
+            Self::Float
+        } else if parse_bool(raw).is_some() {
+            Self::Boolean
+        } else if parse_date(raw).is_some() {
+            Self::Date
+        } else {
+            Self::String
+        }
+    }
+
+    /// 把同一列里的两种类型合并：整数和浮点数合成浮点数，其余不一致一律退回字符串。
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Self::Null, t) | (t, Self::Null) => t,
+            (Self::Integer, Self::Float) | (Self::Float, Self::Integer) => Self::Float,
+            _ => Self::String,
+        }
+    }
+
+    /// schema 里的写法，报错时用。
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Integer => "integer",
+            Self::Float => "float",
+            Self::Boolean => "boolean",
+            Self::Date => "date",
+            Self::Null => "null",
+        }
+    }
+
+    /// 非空值是否符合这个类型；`String` 什么都收，`Null` 什么都不收。
+    ///
+    /// 校验只看能不能解析：`007`、`+1` 都是合法整数。推断时拒绝它们是另一回事，见 [`is_integer`]。
+    pub(crate) fn accepts(self, raw: &str) -> bool {
+        match self {
+            Self::String => true,
+            Self::Integer => raw.parse::<i64>().is_ok(),
+            Self::Float => raw.parse::<f64>().is_ok_and(f64::is_finite),
+            Self::Boolean => parse_bool(raw).is_some(),
+            Self::Date => parse_date(raw).is_some(),
+            Self::Null => false,
+        }
+    }
+
+    /// 按列类型转换一个单元格。`String` 列原样输出；其余列空串变 `null`，转不过去的保留原文。
+    /// 样本之后冒出不符合列类型的值就走这条路，不会报错也不会丢数据。
+    /// `Integer` 列里的小数也算转不过去，不会变成浮点数，免得同一列的类型逐行变化。
+    pub(crate) fn parse(self, raw: &str) -> Value {
+        if self == Self::String {
+            return Value::String(raw.to_owned());
+        }
+        if raw.is_empty() {
+            return Value::Null;
+        }
+        let parsed = match self {
+            Self::Integer if is_integer(raw) => raw.parse::<i64>().ok().map(Value::from),
+            Self::Float => parse_float(raw),
+            Self::Boolean => parse_bool(raw).map(Value::Bool),
+            Self::Date => parse_date(raw).map(|_| Value::String(raw.to_owned())),
+            Self::Integer | Self::String | Self::Null => None,
+        };
+        parsed.unwrap_or_else(|| Value::String(raw.to_owned()))
+    }
+}
+
+/// 对样本逐列推断类型。
+pub(crate) fn infer_columns<'a>(
+    width: usize,
+    rows: impl IntoIterator<Item = &'a csv::StringRecord>,
+) -> Vec<ColumnType> {
+    let mut types = vec![ColumnType::Null; width];
+    for row in rows {
+        for (t, raw) in types.iter_mut().zip(row.iter()) {
+            if !raw.is_empty() {
+                *t = t.merge(ColumnType::of(raw));
+            }
+        }
+    }
+    types
+}
+
+/// 用 schema 覆盖推断结果。schema 里写了表头中不存在的列名直接报错，免得拼错了悄悄不生效。
+///
+/// schema 是一个「列名 → 类型」的映射，JSON / YAML / TOML 均可：
+///
+/// ```yaml
+/// Kit Number: string
+/// DOB: date
+/// ```
+pub(crate) async fn apply_schema(
+    path: &str,
+    headers: &[String],
+    types: &mut [ColumnType],
+) -> Result<()> {
+    let schema: HashMap<String, ColumnType> = read_spec(path).await?;
+    for (column, t) in schema {
+        let Some(i) = headers.iter().position(|h| *h == column) else {
+            bail!("schema column {column:?} is not in the csv header");
+        };
+        types[i] = t;
+    }
+    Ok(())
+}
+
+/// 读一个 JSON / YAML / TOML 写的配置文件，格式看扩展名，没有扩展名按 YAML。
+pub(crate) async fn read_spec<T: DeserializeOwned>(path: &str) -> Result<T> {
+    let content = fs::read_to_string(path)
+        .await
+        .with_context(|| format!("failed to read schema {path}"))?;
+    let format: OutputFormat = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("yaml")
+        .parse()?;
+    let spec = match format {
+        OutputFormat::Json | OutputFormat::Ndjson => serde_json::from_str(&content)?,
+        OutputFormat::Yaml => serde_saphyr::from_str(&content)?,
+        OutputFormat::Toml => toml::from_str(&content)?,
+        OutputFormat::Csv | OutputFormat::Parquet | OutputFormat::Arrow | OutputFormat::Xlsx => {
+            bail!("schema must be JSON, YAML or TOML")
+        }
+    };
+    Ok(spec)
+}
+
+/// 只看写法：整数不接受前导零和正号，`007`、`+1` 多半是编号，当成整数会把零吃掉。
+fn is_integer(raw: &str) -> bool {
+    let digits = raw.strip_prefix('-').unwrap_or(raw);
+    !digits.is_empty()
+        && digits.bytes().all(|b| b.is_ascii_digit())
+        && (digits == "0" || !digits.starts_with('0'))
+}
+
+/// 和整数一样拒绝前导零；`inf`、`NaN` 这类 JSON 表示不了的也不算。
+fn parse_float(raw: &str) -> Option<Value> {
+    let digits = raw.strip_prefix('-').unwrap_or(raw);
+    let leading_zero =
+        digits.starts_with('0') && digits.as_bytes().get(1).is_some_and(u8::is_ascii_digit);
+    if leading_zero || !digits.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    raw.parse::<f64>()
+        .ok()
+        .and_then(Number::from_f64)
+        .map(Value::Number)
+}
+
+fn parse_bool(raw: &str) -> Option<bool> {
+    if raw.eq_ignore_ascii_case("true") {
+        Some(true)
+    } else if raw.eq_ignore_ascii_case("false") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// TOML 的日期时间语法正好是 RFC 3339 的子集，直接借它的解析器；只有时间没有日期的不算。
+pub(crate) fn parse_date(raw: &str) -> Option<toml::value::Datetime> {
+    raw.parse::<toml::value::Datetime>()
+        .ok()
+        .filter(|dt| dt.date.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use csv::StringRecord;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_infer_columns() {
+        let rows = [
+            StringRecord::from(vec!["1", "1.5", "true", "2024-01-31", "007", "", "x"]),
+            StringRecord::from(vec![
+                "-20",
+                "3",
+                "FALSE",
+                "2024-02-01T08:00:00Z",
+                "12",
+                "",
+                "1",
+            ]),
+            StringRecord::from(vec!["", "", "", "", "", "", ""]),
+        ];
+        assert_eq!(
+            infer_columns(7, &rows),
+            [
+                ColumnType::Integer,
+                ColumnType::Float,
+                ColumnType::Boolean,
+                ColumnType::Date,
+                ColumnType::String,
+                ColumnType::Null,
+                ColumnType::String,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_falls_back_to_string() {
+        assert_eq!(ColumnType::Integer.parse("42"), json!(42));
+        assert_eq!(ColumnType::Integer.parse("4.5"), json!("4.5"));
+        assert_eq!(ColumnType::Integer.parse("n/a"), json!("n/a"));
+        assert_eq!(ColumnType::Integer.parse(""), Value::Null);
+        assert_eq!(ColumnType::Boolean.parse("True"), json!(true));
+        assert_eq!(ColumnType::Float.parse("NaN"), json!("NaN"));
+        assert_eq!(ColumnType::String.parse(""), json!(""));
+        assert_eq!(ColumnType::Null.parse("late"), json!("late"));
+    }
+}
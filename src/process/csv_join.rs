@@ -0,0 +1,273 @@
+//! `csv join`：按一列或多列键把两个 CSV 拼起来。
+
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+use csv::StringRecord;
+use serde_json::{Map, Value};
+use tokio::{
+    fs,
+    io::{self, AsyncWrite, BufWriter},
+};
+
+use crate::{
+    cli::{JoinType, OutputFormat},
+    process::{
+        csv_columns::resolve,
+        csv_convert::{CsvRecords, RecordWriter, is_broken_pipe},
+        csv_dialect::CsvReadOptions,
+    },
+};
+
+/// [`process_csv_join`] 的选项。
+#[derive(Debug, Clone)]
+pub struct CsvJoinOptions {
+    pub read: CsvReadOptions,
+    /// 键列：两边同名写 `Name`，不同名写 `Name=Player`（左=右）；列名或从 1 开始的序号
+    pub on: Vec<String>,
+    pub how: JoinType,
+    /// 两边都有的非键列名分别加的后缀
+    pub left_suffix: String,
+    pub right_suffix: String,
+}
+
+impl Default for CsvJoinOptions {
+    fn default() -> Self {
+        Self {
+            read: CsvReadOptions::default(),
+            on: Vec::new(),
+            how: JoinType::Inner,
+            left_suffix: "_left".to_owned(),
+            right_suffix: "_right".to_owned(),
+        }
+    }
+}
+
+/// 右表整个读进内存建哈希索引，左表逐行流式读，所以大的那个放左边。`output` 为 `-` 时写标准输出。
+///
+/// - `inner`：只输出两边都有的键
+/// - `left`：左表每行都输出，右表没有对应的行时右边的列为 `null`
+/// - `full`：在 `left` 的基础上，再把右表里没被匹配过的行补在最后，左边的列为 `null`
+///
+/// 输出列是左表所有列，加上右表除键以外的列；键列只保留一份，用左表的列名。
+///
+/// # Errors
+pub async fn process_csv_join(
+    left: &str,
+    right: &str,
+    output: &str,
+    format: OutputFormat,
+    opts: &CsvJoinOptions,
+) -> Result<()> {
+    if opts.on.is_empty() {
+        bail!("join needs at least one key column");
+    }
+    let mut lefts = CsvRecords::open(left, &opts.read).await?;
+    let mut rights = CsvRecords::open(right, &opts.read).await?;
+    let layout = Layout::new(&lefts.headers, &rights.headers, opts)?;
+
+    let mut right_rows = Vec::new();
+    let mut index: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+    while let Some(record) = rights.next().await {
+        let record = record?;
+        if let Some(key) = key(&record, layout.keys.iter().map(|(_, r)| *r)) {
+            index.entry(key).or_default().push(right_rows.len());
+        }
+        right_rows.push(record);
+    }
+    let mut matched = vec![false; right_rows.len()];
+
+    let to_stdout = output == "-";
+    let sink: Box<dyn AsyncWrite + Unpin + Send> = if to_stdout {
+        Box::new(io::stdout())
+    } else {
+        Box::new(fs::File::create(output).await?)
+    };
+    let mut writer =
+        RecordWriter::new(BufWriter::new(sink), format).with_columns(layout.names.clone());
+    let written = async {
+        while let Some(record) = lefts.next().await {
+            let record = record?;
+            let hits = key(&record, layout.keys.iter().map(|(l, _)| *l))
+                .and_then(|key| index.get(&key))
+                .map_or(&[][..], Vec::as_slice);
+            for &i in hits {
+                matched[i] = true;
+                writer
+                    .write(&layout.row(Some(&record), Some(&right_rows[i])))
+                    .await?;
+            }
+            if hits.is_empty() && opts.how != JoinType::Inner {
+                writer.write(&layout.row(Some(&record), None)).await?;
+            }
+        }
+        if opts.how == JoinType::Full {
+            for (row, _) in right_rows.iter().zip(&matched).filter(|(_, m)| !**m) {
+                writer.write(&layout.row(None, Some(row))).await?;
+            }
+        }
+        writer.finish().await
+    }
+    .await;
+
+    match written {
+        Err(e) if to_stdout && is_broken_pipe(&e) => Ok(()),
+        written => written.map(drop),
+    }
+}
+
+/// 一行的键；有空单元格就返回 `None`，不和任何行匹配，和 SQL 里 `NULL` 不等于 `NULL` 一样。
+fn key(record: &StringRecord, columns: impl Iterator<Item = usize>) -> Option<Vec<String>> {
+    columns
+        .map(|i| record.get(i).filter(|v| !v.is_empty()).map(str::to_owned))
+        .collect()
+}
+
+/// 输出列的排布：每一列从左表还是右表的哪一列取值。
+#[derive(Debug)]
+struct Layout {
+    /// （左表键列，右表键列）
+    keys: Vec<(usize, usize)>,
+    names: Vec<String>,
+    /// 和 `names` 一一对应
+    sources: Vec<Source>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Source {
+    /// 键列：左表没有这一行时取右表的值
+    Key(usize, usize),
+    Left(usize),
+    Right(usize),
+}
+
+impl Layout {
+    fn new(left: &[String], right: &[String], opts: &CsvJoinOptions) -> Result<Self> {
+        let keys = opts
+            .on
+            .iter()
+            .map(|on| {
+                let (l, r) = on.split_once('=').unwrap_or((on, on));
+                Ok((resolve(left, l)?, resolve(right, r)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let right_rest: Vec<usize> = (0..right.len())
+            .filter(|r| !keys.iter().any(|(_, k)| k == r))
+            .collect();
+        let clashes = |name: &str| {
+            right_rest.iter().any(|r| right[*r] == name) && left.iter().any(|l| l == name)
+        };
+
+        let mut names = Vec::new();
+        let mut sources = Vec::new();
+        for (i, name) in left.iter().enumerate() {
+            match keys.iter().find(|(l, _)| *l == i) {
+                Some((l, r)) => sources.push(Source::Key(*l, *r)),
+                None => sources.push(Source::Left(i)),
+            }
+            names.push(if clashes(name) {
+                format!("{name}{}", opts.left_suffix)
+            } else {
+                name.clone()
+            });
+        }
+        for &r in &right_rest {
+            let name = &right[r];
+            sources.push(Source::Right(r));
+            names.push(if clashes(name) {
+                format!("{name}{}", opts.right_suffix)
+            } else {
+                name.clone()
+            });
+        }
+
+        for (n, name) in names.iter().enumerate() {
+            if names[..n].contains(name) {
+                bail!("duplicate output column {name:?}, pick different suffixes");
+            }
+        }
+        Ok(Self {
+            keys,
+            names,
+            sources,
+        })
+    }
+
+    fn row(&self, left: Option<&StringRecord>, right: Option<&StringRecord>) -> Value {
+        let get = |record: Option<&StringRecord>, i: usize| {
+            record
+                .and_then(|r| r.get(i))
+                .map_or(Value::Null, |v| Value::String(v.to_owned()))
+        };
+        let mut row = Map::new();
+        for (name, source) in self.names.iter().zip(&self.sources) {
+            let value = match *source {
+                Source::Key(l, _) if left.is_some() => get(left, l),
+                Source::Key(_, r) | Source::Right(r) => get(right, r),
+                Source::Left(l) => get(left, l),
+            };
+            row.insert(name.clone(), value);
+        }
+        Value::Object(row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    async fn join(how: JoinType, on: &str) -> Result<String> {
+        let dir = tempfile::tempdir()?;
+        let roster = dir.path().join("roster.csv");
+        let stats = dir.path().join("stats.csv");
+        let out = dir.path().join("out.csv");
+        fs::write(
+            &roster,
+            "Name,Team,Kit\nBuffon,Juve,1\nPerin,Juve,37\n,Juve,99\n",
+        )
+        .await?;
+        fs::write(&stats, "Player,Team,Goals\nBuffon,PSG,0\nRonaldo,Juve,28\n").await?;
+
+        let opts = CsvJoinOptions {
+            on: vec![on.to_owned()],
+            how,
+            ..CsvJoinOptions::default()
+        };
+        process_csv_join(
+            roster.to_str().context("utf-8 path")?,
+            stats.to_str().context("utf-8 path")?,
+            out.to_str().context("utf-8 path")?,
+            OutputFormat::Csv,
+            &opts,
+        )
+        .await?;
+        Ok(fs::read_to_string(&out).await?)
+    }
+
+    #[tokio::test]
+    async fn test_join_types() -> Result<()> {
+        assert_eq!(
+            join(JoinType::Inner, "Name=Player").await?,
+            "Name,Team_left,Kit,Team_right,Goals\nBuffon,Juve,1,PSG,0\n"
+        );
+        assert_eq!(
+            join(JoinType::Left, "Name=Player").await?,
+            "Name,Team_left,Kit,Team_right,Goals\nBuffon,Juve,1,PSG,0\nPerin,Juve,37,,\n,Juve,99,,\
+             \n"
+        );
+        assert_eq!(
+            join(JoinType::Full, "1=1").await?,
+            "Name,Team_left,Kit,Team_right,Goals\nBuffon,Juve,1,PSG,0\nPerin,Juve,37,,\n,Juve,99,,\
+             \nRonaldo,,,Juve,28\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_join_rejects_unknown_key() {
+        assert!(join(JoinType::Inner, "Name").await.is_err());
+    }
+}
@@ -0,0 +1,257 @@
+//! `csv --nested`：把点号和方括号列名还原成嵌套结构，是 `csv from --nested` 的反方向。
+
+use anyhow::{Result, bail};
+use serde_json::{Map, Value};
+
+/// 列名拆出来的一段路径。
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// 按列名搭好的结构模板，每条记录往里填值。
+#[derive(Debug)]
+pub(crate) struct Nesting {
+    root: Vec<(String, Node)>,
+}
+
+#[derive(Debug)]
+enum Node {
+    /// 第几个输出列
+    Leaf(usize),
+    Object(Vec<(String, Node)>),
+    /// （下标，元素），按出现顺序，填值时再排
+    Array(Vec<(usize, Node)>),
+}
+
+impl Nesting {
+    /// `columns` 是按输出顺序排的列名：`address.city` 拼成对象，`tags[0]` 拼成数组，可以混着写
+    /// （`players[0].name`）。
+    ///
+    /// 一列既当叶子又当对象（`address` 和 `address.city` 同时存在）、同一个位置既当对象又当数组时
+    /// 报错；下标来自不可信的表头，超过表头的列数（数组再长也填不满）也报错，免得按下标分配内存。
+    pub(crate) fn new<'a>(columns: impl IntoIterator<Item = &'a str>) -> Result<Self> {
+        let columns: Vec<&str> = columns.into_iter().collect();
+        let mut root = Vec::new();
+        for (column, name) in columns.iter().enumerate() {
+            let (key, rest) = parse(name).unwrap_or_else(|| ((*name).to_owned(), Vec::new()));
+            if let Some(index) = rest.iter().find_map(|segment| match segment {
+                Segment::Index(i) if *i >= columns.len() => Some(i),
+                _ => None,
+            }) {
+                bail!(
+                    "column {name:?} uses index {index}, but --nested arrays can't be longer than \
+                     the {} columns in the header",
+                    columns.len()
+                );
+            }
+            if let Err(other) = insert(&mut root, &key, &rest, column) {
+                bail!(
+                    "column {name:?} conflicts with {:?} in --nested output",
+                    columns[other]
+                );
+            }
+        }
+        Ok(Self { root })
+    }
+
+    /// 把按输出列排好的扁平记录（[`Projection::row`] 的结果）变成嵌套的。
+    ///
+    /// [`Projection::row`]: super::csv_columns::Projection::row
+    pub(crate) fn nest(&self, flat: Value) -> Value {
+        let Value::Object(map) = flat else {
+            return flat;
+        };
+        let mut values: Vec<Option<Value>> = map.into_iter().map(|(_, v)| Some(v)).collect();
+        object(&self.root, &mut values)
+    }
+}
+
+fn object(fields: &[(String, Node)], values: &mut [Option<Value>]) -> Value {
+    let map: Map<String, Value> = fields
+        .iter()
+        .map(|(key, node)| (key.clone(), fill(node, values)))
+        .collect();
+    Value::Object(map)
+}
+
+/// 数组末尾的空单元格是表格里补齐用的，去掉；中间的空单元格保留，位置不变。
+fn fill(node: &Node, values: &mut [Option<Value>]) -> Value {
+    match node {
+        Node::Leaf(column) => values
+            .get_mut(*column)
+            .and_then(Option::take)
+            .unwrap_or(Value::Null),
+        Node::Object(fields) => object(fields, values),
+        Node::Array(items) => {
+            let len = items.iter().map(|(i, _)| i + 1).max().unwrap_or_default();
+            let mut array = vec![Value::Null; len];
+            for (i, item) in items {
+                array[*i] = fill(item, values);
+            }
+            while array.last().is_some_and(is_blank) {
+                array.pop();
+            }
+            Value::Array(array)
+        }
+    }
+}
+
+fn is_blank(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.is_empty(),
+        _ => false,
+    }
+}
+
+/// 冲突时返回先占了这个位置的列。
+fn insert(
+    fields: &mut Vec<(String, Node)>,
+    key: &str,
+    rest: &[Segment],
+    column: usize,
+) -> Result<(), usize> {
+    let n = fields
+        .iter()
+        .position(|(k, _)| k == key)
+        .unwrap_or_else(|| {
+            fields.push((key.to_owned(), empty(rest, column)));
+            fields.len() - 1
+        });
+    descend(&mut fields[n].1, rest, column)
+}
+
+fn descend(node: &mut Node, path: &[Segment], column: usize) -> Result<(), usize> {
+    match (node, path) {
+        (Node::Leaf(c), []) if *c == column => Ok(()),
+        (Node::Object(fields), [Segment::Key(key), rest @ ..]) => insert(fields, key, rest, column),
+        (Node::Array(items), [Segment::Index(i), rest @ ..]) => {
+            let n = items.iter().position(|(j, _)| j == i).unwrap_or_else(|| {
+                items.push((*i, empty(rest, column)));
+                items.len() - 1
+            });
+            descend(&mut items[n].1, rest, column)
+        }
+        (node, _) => Err(first_column(node)),
+    }
+}
+
+/// 还没有任何列的位置：按路径下一段决定是叶子、对象还是数组。
+fn empty(rest: &[Segment], column: usize) -> Node {
+    match rest.first() {
+        None => Node::Leaf(column),
+        Some(Segment::Key(_)) => Node::Object(Vec::new()),
+        Some(Segment::Index(_)) => Node::Array(Vec::new()),
+    }
+}
+
+fn first_column(node: &Node) -> usize {
+    match node {
+        Node::Leaf(column) => *column,
+        Node::Object(fields) => fields.first().map_or(0, |(_, n)| first_column(n)),
+        Node::Array(items) => items.first().map_or(0, |(_, n)| first_column(n)),
+    }
+}
+
+/// `players[0].name` → `players` 和 `[0]`、`name`；写法不成路径（`No.`、`a..b`、`[0]`、`a[x]`）
+/// 返回 `None`，这样的列名原样当一个顶层键。
+fn parse(name: &str) -> Option<(String, Vec<Segment>)> {
+    let mut path = Vec::new();
+    let mut rest = name;
+    loop {
+        let end = rest.find(['.', '[']).unwrap_or(rest.len());
+        let key = &rest[..end];
+        if key.is_empty() {
+            return None;
+        }
+        path.push(Segment::Key(key.to_owned()));
+        rest = &rest[end..];
+        while let Some(inner) = rest.strip_prefix('[') {
+            let close = inner.find(']')?;
+            let index = &inner[..close];
+            if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            path.push(Segment::Index(index.parse().ok()?));
+            rest = &inner[close + 1..];
+        }
+        match rest.strip_prefix('.') {
+            Some(next) => rest = next,
+            None if rest.is_empty() => break,
+            None => return None,
+        }
+    }
+    match path.remove(0) {
+        Segment::Key(key) => Some((key, path)),
+        Segment::Index(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_nest_objects_and_arrays() -> Result<()> {
+        let nesting = Nesting::new([
+            "name",
+            "address.city",
+            "tags[1]",
+            "tags[0]",
+            "tags[2]",
+            "players[0].name",
+            "address.zip",
+            "No.",
+        ])?;
+        let row = json!({
+            "name": "Juve",
+            "address.city": "Turin",
+            "tags[1]": "b",
+            "tags[0]": "",
+            "tags[2]": "",
+            "players[0].name": "Buffon",
+            "address.zip": null,
+            "No.": 1,
+        });
+        assert_eq!(
+            nesting.nest(row),
+            json!({
+                "name": "Juve",
+                "address": { "city": "Turin", "zip": null },
+                "tags": ["", "b"],
+                "players": [{ "name": "Buffon" }],
+                "No.": 1,
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_nest_rejects_conflicting_columns() {
+        assert!(Nesting::new(["address", "address.city"]).is_err());
+        assert!(Nesting::new(["tags[0]", "tags.first"]).is_err());
+        assert!(Nesting::new(["a.b", "a.b"]).is_err());
+        assert!(Nesting::new(["a..b", "[0]", "a[x]"]).is_ok());
+    }
+
+    #[test]
+    fn test_nest_rejects_huge_indices() -> Result<()> {
+        let err = Nesting::new(["x[99999999999]"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "column \"x[99999999999]\" uses index 99999999999, but --nested arrays can't be \
+             longer than the 1 columns in the header"
+        );
+        // 中间留空的下标在列数以内照样可以
+        let nesting = Nesting::new(["tags[0]", "tags[2]", "name"])?;
+        assert_eq!(
+            nesting.nest(json!({ "tags[0]": "a", "tags[2]": "c", "name": "x" })),
+            json!({ "tags": ["a", null, "c"], "name": "x" })
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+//! `csv show`：在终端里把 CSV 画成一张带边框的表，方便肉眼看数据。
+
+use std::collections::VecDeque;
+
+use anyhow::{Result, bail};
+use csv::StringRecord;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::process::{
+    csv_convert::CsvRecords,
+    csv_dialect::CsvReadOptions,
+    csv_infer::{ColumnType, infer_columns},
+};
+
+/// 截断后每列至少保留的显示宽度（含 `…`）。
+const MIN_COLUMN_WIDTH: usize = 3;
+
+/// 显示哪一段行。`Head` 读够就停，`Tail` 用定长队列只留最后几行，都不会把整个文件读进内存。
+#[derive(Debug, Clone, Copy, Default)]
+pub enum CsvShowRange {
+    #[default]
+    All,
+    Head(usize),
+    Tail(usize),
+}
+
+/// 渲染整张表并返回。`max_width` 是终端宽度，`None` 表示不截断。
+///
+/// # Errors
+pub async fn process_csv_show(
+    input: &str,
+    read: &CsvReadOptions,
+    range: CsvShowRange,
+    max_width: Option<usize>,
+) -> Result<String> {
+    let mut records = CsvRecords::open(input, read).await?;
+    let mut rows = VecDeque::new();
+    while let Some(record) = records.next().await {
+        let record = record?;
+        match range {
+            CsvShowRange::Head(n) if rows.len() >= n => break,
+            CsvShowRange::Tail(0) => continue,
+            CsvShowRange::Tail(n) if rows.len() >= n => {
+                rows.pop_front();
+            }
+            _ => {}
+        }
+        rows.push_back(record);
+    }
+    render(&records.headers, rows.make_contiguous(), max_width)
+}
+
+/// 列宽按显示宽度算，中文、emoji 这类双宽字符也能对齐。数字列右对齐，其余左对齐；
+/// 类型沿用 `--infer` 的推断规则。
+fn render(headers: &[String], rows: &[StringRecord], max_width: Option<usize>) -> Result<String> {
+    if headers.is_empty() {
+        bail!("csv has no columns");
+    }
+    let right: Vec<bool> = infer_columns(headers.len(), rows)
+        .into_iter()
+        .map(|t| matches!(t, ColumnType::Integer | ColumnType::Float))
+        .collect();
+    let headers: Vec<String> = headers.iter().map(|h| flatten(h)).collect();
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            (0..headers.len())
+                .map(|i| flatten(row.get(i).unwrap_or_default()))
+                .collect()
+        })
+        .collect();
+
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.width()).collect();
+    for row in &rows {
+        for (w, value) in widths.iter_mut().zip(row) {
+            *w = (*w).max(value.width());
+        }
+    }
+    if let Some(max_width) = max_width {
+        shrink(&mut widths, max_width);
+    }
+
+    let mut out = String::new();
+    border(&mut out, &widths, ['┌', '┬', '┐']);
+    line(&mut out, &widths, &vec![false; widths.len()], &headers);
+    border(&mut out, &widths, ['├', '┼', '┤']);
+    for row in &rows {
+        line(&mut out, &widths, &right, row);
+    }
+    border(&mut out, &widths, ['└', '┴', '┘']);
+    Ok(out)
+}
+
+/// 表头和单元格里的换行、制表符会把表撑乱，统一换成空格。
+fn flatten(value: &str) -> String {
+    value.replace(['\r', '\n', '\t'], " ")
+}
+
+/// 每列占 `宽度 + 3`（两侧空格和一条竖线），再加最左边一条竖线。
+/// 超出终端宽度就反复收窄当前最宽的一列，直到放得下或者每列都到了下限。
+fn shrink(widths: &mut [usize], max_width: usize) {
+    let total = |widths: &[usize]| widths.iter().map(|w| w + 3).sum::<usize>() + 1;
+    while total(widths) > max_width {
+        let Some(widest) = widths
+            .iter_mut()
+            .filter(|w| **w > MIN_COLUMN_WIDTH)
+            .max_by_key(|w| **w)
+        else {
+            break;
+        };
+        *widest -= 1;
+    }
+}
+
+fn border(out: &mut String, widths: &[usize], [left, mid, right]: [char; 3]) {
+    out.push(left);
+    for (i, w) in widths.iter().enumerate() {
+        if i > 0 {
+            out.push(mid);
+        }
+        out.push_str(&"─".repeat(w + 2));
+    }
+    out.push(right);
+    out.push('\n');
+}
+
+fn line<S: AsRef<str>>(out: &mut String, widths: &[usize], right: &[bool], cells: &[S]) {
+    out.push('│');
+    for ((w, right), value) in widths.iter().zip(right).zip(cells) {
+        let value = truncate(value.as_ref(), *w);
+        let pad = " ".repeat(w - value.width());
+        out.push(' ');
+        if *right {
+            out.push_str(&pad);
+            out.push_str(&value);
+        } else {
+            out.push_str(&value);
+            out.push_str(&pad);
+        }
+        out.push_str(" │");
+    }
+    out.push('\n');
+}
+
+/// 按显示宽度截断，超出的部分换成 `…`；双宽字符放不下半个就整个丢掉。
+fn truncate(value: &str, width: usize) -> String {
+    if value.width() <= width {
+        return value.to_owned();
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    for c in value.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w > width - 1 {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    out.push('…');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(rows: &[&[&str]]) -> Vec<StringRecord> {
+        rows.iter()
+            .map(|r| StringRecord::from(r.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_render_aligns_columns() -> Result<()> {
+        let headers = ["Name".to_owned(), "Kit Number".to_owned()];
+        let table = render(&headers, &rows(&[&["布冯", "1"], &["Dybala", "10"]]), None)?;
+        assert_eq!(
+            table,
+            "┌────────┬────────────┐\n│ Name   │ Kit Number │\n├────────┼────────────┤\n│ 布冯   \
+             │          1 │\n│ Dybala │         10 │\n└────────┴────────────┘\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_truncates_to_width() -> Result<()> {
+        let headers = ["a".to_owned(), "b".to_owned()];
+        let table = render(&headers, &rows(&[&["x", "a long long value"]]), Some(20))?;
+        for line in table.lines() {
+            assert!(line.width() <= 20, "{line}");
+        }
+        assert!(table.contains("│ x │ a long long… │"), "{table}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_flattens_headers_and_cells() -> Result<()> {
+        let headers = ["Kit\nNumber".to_owned()];
+        let table = render(&headers, &rows(&[&["1\t2"]]), None)?;
+        assert_eq!(
+            table,
+            "┌────────────┐\n│ Kit Number │\n├────────────┤\n│ 1 2        │\n└────────────┘\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_process_csv_show_head_and_tail() -> Result<()> {
+        let head = process_csv_show(
+            "assets/juventus.csv",
+            &CsvReadOptions::default(),
+            CsvShowRange::Head(2),
+            None,
+        )
+        .await?;
+        // 上下边框、表头、分隔线，加两行数据
+        assert_eq!(head.lines().count(), 6);
+        assert!(head.contains("Wojciech Szczesny"), "{head}");
+
+        let tail = process_csv_show(
+            "assets/juventus.csv",
+            &CsvReadOptions::default(),
+            CsvShowRange::Tail(1),
+            None,
+        )
+        .await?;
+        assert_eq!(tail.lines().count(), 5);
+        assert!(!tail.contains("Wojciech Szczesny"), "{tail}");
+        Ok(())
+    }
+}
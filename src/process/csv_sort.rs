@@ -0,0 +1,420 @@
+//! `csv --sort-by` / `--dedup`：输出前排序、去重。
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashSet},
+    fs::File,
+    io::{BufReader, BufWriter, Seek},
+    mem,
+};
+
+use anyhow::{Context, Result};
+use csv::StringRecord;
+use tokio::{sync::mpsc, task};
+
+use crate::process::{
+    csv_columns::resolve,
+    csv_convert::{CHANNEL_CAPACITY, CsvRecords},
+    csv_filter::Filter,
+};
+
+/// 不指定时排序最多在内存里攒多少字节的行。
+pub(crate) const DEFAULT_SORT_MEMORY: usize = 256 * 1024 * 1024;
+
+/// 一次最多归并多少段，同时打开的临时文件也就这么多；段数再多就分几轮归并。
+const MAX_FAN_IN: usize = 64;
+
+/// 解析好的 `--sort-by`。
+#[derive(Debug, Clone)]
+pub(crate) struct SortOrder {
+    keys: Vec<SortKey>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SortKey {
+    column: usize,
+    desc: bool,
+}
+
+/// 排序键里的一格，读进来时解析一次，比较时不再重复解析数字。
+#[derive(Debug, Clone)]
+enum Cell {
+    Number(f64),
+    Text(String),
+    Empty,
+}
+
+/// 一行和它的排序键。
+type Keyed = (Vec<Cell>, StringRecord);
+
+/// 归并时的一段有序记录：临时文件或者最后留在内存里的那段。
+type Source = Box<dyn Iterator<Item = csv::Result<StringRecord>> + Send>;
+
+/// 归并堆里每一段的当前行。`BinaryHeap` 是大顶堆，所以比较反过来；键相同时靠前的段先出，
+/// 归并后仍然稳定。
+struct Head<'a> {
+    order: &'a SortOrder,
+    key: Vec<Cell>,
+    source: usize,
+    record: StringRecord,
+}
+
+impl Ord for Head<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.order
+            .compare(&other.key, &self.key)
+            .then(other.source.cmp(&self.source))
+    }
+}
+
+impl PartialOrd for Head<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Head<'_> {}
+
+impl SortOrder {
+    /// 排序键写成 `列[:desc]`，前面的优先；列名或从 1 开始的序号，按原表头写。没有排序键时返回
+    /// `None`。
+    pub(crate) fn new(headers: &[String], sort_by: &[String]) -> Result<Option<Self>> {
+        if sort_by.is_empty() {
+            return Ok(None);
+        }
+        let keys = sort_by
+            .iter()
+            .map(|spec| {
+                // 列名里本身可能带冒号，只认末尾的 `:asc` / `:desc`
+                let (column, desc) = match spec.rsplit_once(':') {
+                    Some((column, dir)) if dir.eq_ignore_ascii_case("desc") => (column, true),
+                    Some((column, dir)) if dir.eq_ignore_ascii_case("asc") => (column, false),
+                    _ => (spec.as_str(), false),
+                };
+                Ok(SortKey {
+                    column: resolve(headers, column)?,
+                    desc,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Some(Self { keys }))
+    }
+
+    /// 读完 `records`（只留下满足 `filter` 的行）排好序，返回按新顺序吐记录的 [`CsvRecords`]。
+    /// 排序是稳定的，键相同的行保持输入顺序。
+    ///
+    /// 攒下的行超过 `budget` 字节就排好写进临时文件。同一轮的段攒够 [`MAX_FAN_IN`] 个就先归并成
+    /// 下一轮的一段，文件句柄不会随段数一直涨；最后的归并放在 `spawn_blocking` 里，边归并边交出去。
+    pub(crate) async fn sort(
+        &self,
+        mut records: CsvRecords,
+        filter: Option<Filter>,
+        budget: usize,
+    ) -> Result<CsvRecords> {
+        let mut buffer: Vec<Keyed> = Vec::new();
+        let mut size = 0;
+        // 每段带着它是第几轮归并出来的；越早的段轮次越高，按输入顺序排着
+        let mut runs: Vec<(usize, File)> = Vec::new();
+        while let Some(record) = records.next().await {
+            let record = record?;
+            if filter.as_ref().is_some_and(|f| !f.matches(&record)) {
+                continue;
+            }
+            size += footprint(&record);
+            buffer.push((self.key(&record), record));
+            if size >= budget {
+                let (order, chunk) = (self.clone(), mem::take(&mut buffer));
+                runs.push((0, task::spawn_blocking(move || order.spill(chunk)).await??));
+                size = 0;
+                while let Some(&(level, _)) = runs.last() {
+                    let same = runs.iter().rev().take_while(|(l, _)| *l == level).count();
+                    if same < MAX_FAN_IN {
+                        break;
+                    }
+                    let group: Vec<File> = runs
+                        .split_off(runs.len() - same)
+                        .into_iter()
+                        .map(|(_, run)| run)
+                        .collect();
+                    let order = self.clone();
+                    runs.push((
+                        level + 1,
+                        task::spawn_blocking(move || order.compact(group)).await??,
+                    ));
+                }
+            }
+        }
+
+        // 每一轮都没攒满，但加起来还可能超过上限，再按顺序每 MAX_FAN_IN
+        // 段并一次；内存里那段也占一路
+        let mut runs: Vec<File> = runs.into_iter().map(|(_, run)| run).collect();
+        while runs.len() >= MAX_FAN_IN {
+            let mut merged = Vec::new();
+            let mut rest = runs.into_iter().peekable();
+            while rest.peek().is_some() {
+                let group: Vec<File> = rest.by_ref().take(MAX_FAN_IN).collect();
+                let order = self.clone();
+                merged.push(task::spawn_blocking(move || order.compact(group)).await??);
+            }
+            runs = merged;
+        }
+
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let order = self.clone();
+        task::spawn_blocking(move || order.merge(buffer, runs, &tx));
+        Ok(records.with_source(rx))
+    }
+
+    fn key(&self, record: &StringRecord) -> Vec<Cell> {
+        self.keys
+            .iter()
+            .map(|key| {
+                let raw = record.get(key.column).unwrap_or_default();
+                match raw.trim().parse::<f64>() {
+                    Ok(n) if n.is_finite() => Cell::Number(n),
+                    _ if raw.is_empty() => Cell::Empty,
+                    _ => Cell::Text(raw.to_owned()),
+                }
+            })
+            .collect()
+    }
+
+    /// 两边都是数字按数值比，否则按字符串比；同一列里数字排在文本前，空单元格不论升降序都排最后。
+    fn compare(&self, a: &[Cell], b: &[Cell]) -> Ordering {
+        self.keys
+            .iter()
+            .zip(a.iter().zip(b))
+            .map(|(key, (a, b))| match (a, b) {
+                (Cell::Number(x), Cell::Number(y)) => direction(x.total_cmp(y), key.desc),
+                (Cell::Text(x), Cell::Text(y)) => direction(x.cmp(y), key.desc),
+                // 空值总在最后，不跟着升降序反过来
+                (Cell::Empty, _) | (_, Cell::Empty) => rank(a).cmp(&rank(b)),
+                _ => direction(rank(a).cmp(&rank(b)), key.desc),
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+
+    /// 排好一段写进临时文件，返回倒回开头的文件。
+    fn spill(&self, mut chunk: Vec<Keyed>) -> Result<File> {
+        chunk.sort_by(|a, b| self.compare(&a.0, &b.0));
+        let mut writer = csv::Writer::from_writer(BufWriter::new(
+            tempfile::tempfile().context("failed to create a temporary file for sorting")?,
+        ));
+        for (_, record) in &chunk {
+            writer.write_record(record)?;
+        }
+        let mut file = writer
+            .into_inner()
+            .context("failed to flush sort run")?
+            .into_inner()?;
+        file.rewind()?;
+        Ok(file)
+    }
+
+    /// 把几段归并成一段，写进新的临时文件，返回倒回开头的文件。
+    fn compact(&self, runs: Vec<File>) -> Result<File> {
+        let mut writer = csv::Writer::from_writer(BufWriter::new(
+            tempfile::tempfile().context("failed to create a temporary file for sorting")?,
+        ));
+        self.merge_sources(runs.into_iter().map(run_records).collect(), |record| {
+            writer.write_record(&record)?;
+            Ok(true)
+        })?;
+        let mut file = writer
+            .into_inner()
+            .context("failed to flush sort run")?
+            .into_inner()?;
+        file.rewind()?;
+        Ok(file)
+    }
+
+    /// 把各个临时文件和最后留在内存里的一段归并起来，逐条发给 `tx`。
+    fn merge(
+        &self,
+        mut buffer: Vec<Keyed>,
+        runs: Vec<File>,
+        tx: &mpsc::Sender<csv::Result<StringRecord>>,
+    ) {
+        buffer.sort_by(|a, b| self.compare(&a.0, &b.0));
+        // 按输入顺序排列各段，内存里的一段最晚
+        let mut sources: Vec<Source> = runs.into_iter().map(run_records).collect();
+        sources.push(Box::new(buffer.into_iter().map(|(_, record)| Ok(record))));
+        // 接收端已经不要了（下游出错提前返回），没必要再归并下去
+        let merged = self.merge_sources(sources, |record| Ok(tx.blocking_send(Ok(record)).is_ok()));
+        if let Err(e) = merged {
+            drop(tx.blocking_send(Err(e)));
+        }
+    }
+
+    /// 归并有序的各段，按顺序交给 `emit`；`emit` 返回 `false` 就提前停下。
+    fn merge_sources(
+        &self,
+        mut sources: Vec<Source>,
+        mut emit: impl FnMut(StringRecord) -> csv::Result<bool>,
+    ) -> csv::Result<()> {
+        let mut heads = BinaryHeap::with_capacity(sources.len());
+        for (source, records) in sources.iter_mut().enumerate() {
+            if let Some(record) = records.next().transpose()? {
+                heads.push(self.head(source, record));
+            }
+        }
+        while let Some(Head { source, record, .. }) = heads.pop() {
+            if !emit(record)? {
+                return Ok(());
+            }
+            if let Some(record) = sources[source].next().transpose()? {
+                heads.push(self.head(source, record));
+            }
+        }
+        Ok(())
+    }
+
+    fn head(&self, source: usize, record: StringRecord) -> Head<'_> {
+        Head {
+            order: self,
+            key: self.key(&record),
+            source,
+            record,
+        }
+    }
+}
+
+/// 读回一段临时文件。
+fn run_records(run: File) -> Source {
+    let reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(BufReader::new(run));
+    Box::new(reader.into_records())
+}
+
+fn direction(ordering: Ordering, desc: bool) -> Ordering {
+    if desc { ordering.reverse() } else { ordering }
+}
+
+fn rank(cell: &Cell) -> u8 {
+    match cell {
+        Cell::Number(_) => 0,
+        Cell::Text(_) => 1,
+        Cell::Empty => 2,
+    }
+}
+
+/// 一行在内存里大约占多少字节：字段内容加上每个字段的下标，再加上 `Vec` 之类的固定开销。
+fn footprint(record: &StringRecord) -> usize {
+    record.as_slice().len() * 2 + record.len() * 2 * size_of::<usize>() + 64
+}
+
+/// `--dedup`：记住见过的键，只放过每个键第一次出现的行，和 `--sort-by` 一起用时就是排序后的第一行。
+/// 内存随不同键的个数增长。
+#[derive(Debug)]
+pub(crate) struct Dedup {
+    /// 空表示整行都算键
+    columns: Vec<usize>,
+    seen: HashSet<Vec<String>>,
+}
+
+impl Dedup {
+    /// `columns` 为空时整行去重。
+    pub(crate) fn new(headers: &[String], columns: &[String]) -> Result<Self> {
+        let columns = columns
+            .iter()
+            .map(|column| resolve(headers, column))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            columns,
+            seen: HashSet::new(),
+        })
+    }
+
+    /// 这一行的键是不是第一次出现。
+    pub(crate) fn first(&mut self, record: &StringRecord) -> bool {
+        let key: Vec<String> = if self.columns.is_empty() {
+            record.iter().map(str::to_owned).collect()
+        } else {
+            self.columns
+                .iter()
+                .map(|&i| record.get(i).unwrap_or_default().to_owned())
+                .collect()
+        };
+        self.seen.insert(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::CsvReadOptions;
+
+    async fn sorted(csv: &str, sort_by: &[&str], budget: usize) -> Result<Vec<String>> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.csv");
+        std::fs::write(&input, csv)?;
+        let mut records = CsvRecords::open(
+            input.to_str().context("utf-8 path")?,
+            &CsvReadOptions::default(),
+        )
+        .await?;
+        let sort_by: Vec<String> = sort_by.iter().map(|s| (*s).to_owned()).collect();
+        let order = SortOrder::new(&records.headers, &sort_by)?.context("has keys")?;
+        records = order.sort(records, None, budget).await?;
+        let mut names = Vec::new();
+        while let Some(record) = records.next().await {
+            names.push(record?[0].to_owned());
+        }
+        Ok(names)
+    }
+
+    #[tokio::test]
+    async fn test_sort_numeric_aware_and_stable() -> Result<()> {
+        let csv = "name,kit,pos\na,10,GK\nb,9,DF\nc,,GK\nd,x,DF\ne,10,DF\nf,-1.5,GK\n";
+        // 数字按数值，文本在数字后，空值最后；10 的两行保持输入顺序
+        let expected = ["f", "b", "a", "e", "d", "c"];
+        assert_eq!(sorted(csv, &["kit"], DEFAULT_SORT_MEMORY).await?, expected);
+        // 预算小到每行一个临时文件，归并结果和全在内存里排一样
+        assert_eq!(sorted(csv, &["kit"], 1).await?, expected);
+
+        // 降序时文本排到数字前面，空值仍在最后
+        let expected = ["d", "e", "b", "a", "f", "c"];
+        assert_eq!(sorted(csv, &["pos", "2:desc"], 1).await?, expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sort_merges_more_runs_than_fan_in() -> Result<()> {
+        // 每行一段，段数是 MAX_FAN_IN 的好几倍，要分几轮归并；键只有 7 种，顺带验证稳定
+        let rows: Vec<(usize, usize)> = (0..MAX_FAN_IN * 5).map(|i| (i, i * 37 % 7)).collect();
+        let csv: String = std::iter::once("name,key\n".to_owned())
+            .chain(rows.iter().map(|(name, key)| format!("{name},{key}\n")))
+            .collect();
+        let mut expected = rows.clone();
+        expected.sort_by_key(|&(_, key)| key);
+        let expected: Vec<String> = expected.iter().map(|(name, _)| name.to_string()).collect();
+        assert_eq!(sorted(&csv, &["key"], 1).await?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedup_by_columns_or_whole_row() -> Result<()> {
+        let headers = ["name".to_owned(), "kit".to_owned()];
+        let rows = [["a", "1"], ["a", "2"], ["a", "1"], ["b", "1"]]
+            .map(|row| StringRecord::from(&row[..]));
+
+        let mut by_name = Dedup::new(&headers, &["name".to_owned()])?;
+        let kept: Vec<bool> = rows.iter().map(|row| by_name.first(row)).collect();
+        assert_eq!(kept, [true, false, false, true]);
+
+        let mut whole = Dedup::new(&headers, &[])?;
+        let kept: Vec<bool> = rows.iter().map(|row| whole.first(row)).collect();
+        assert_eq!(kept, [true, true, false, true]);
+
+        assert!(Dedup::new(&headers, &["nmae".to_owned()]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,519 @@
+//! `csv split`：把一个大 CSV 按行数、字节数或列值切成几份写进一个目录，每份都带表头。
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{AsyncWriteExt, BufWriter},
+};
+
+use crate::process::{
+    csv_columns::resolve,
+    csv_convert::{CsvRecords, csv_line},
+    csv_dialect::CsvReadOptions,
+};
+
+/// 按列值分时最多同时开着多少个文件，离一般系统默认的 1024 个文件描述符留足余量；超过就全部关掉，
+/// 之后用到再追加打开。
+const MAX_OPEN_FILES: usize = 256;
+
+/// [`process_csv_split`] 的选项。
+#[derive(Debug, Clone)]
+pub struct CsvSplitOptions {
+    pub read: CsvReadOptions,
+    pub by: SplitBy,
+    /// 文件名模板，可以用 `{stem}`（输入文件名去掉扩展名）、`{n}` / `{n:04}`（第几份）和
+    /// `{value}`（列值）；不给时按行数、字节分是 `{stem}-{n}.csv`，按列值分是 `{stem}-{value}.csv`
+    pub name: Option<String>,
+    /// 目录里已经有同名文件时覆盖；否则报错，一个文件都不动
+    pub force: bool,
+}
+
+/// 怎么切。
+#[derive(Debug, Clone)]
+pub enum SplitBy {
+    /// 每份最多多少行
+    Rows(usize),
+    /// 每份连表头最多多少字节
+    Bytes(u64),
+    /// 按这一列的值分；列名或从 1 开始的序号
+    Column(String),
+}
+
+/// 写出的一份。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitPart {
+    pub path: PathBuf,
+    /// 数据行数，不算表头
+    pub rows: usize,
+}
+
+/// 逐行读、逐行写，不把整个文件读进内存。各份统一写成逗号分隔的 UTF-8 CSV，输入没有表头时各份也
+/// 不加表头。按顺序返回写出的各份；输入一行数据都没有时不写文件。
+///
+/// 各份先写进 `dir` 里的临时目录，全部写完再挪过去，中途出错时 `dir` 里什么都不留。
+///
+/// # Errors
+pub async fn process_csv_split(
+    input: &str,
+    dir: &Path,
+    opts: &CsvSplitOptions,
+) -> Result<Vec<SplitPart>> {
+    match opts.by {
+        SplitBy::Rows(0) => bail!("--rows must be at least 1"),
+        SplitBy::Bytes(0) => bail!("--bytes must be at least 1"),
+        _ => {}
+    }
+    let template = Template::parse(opts.name.as_deref(), &opts.by)?;
+    let mut records = CsvRecords::open(input, &opts.read).await?;
+    let stem = Path::new(input)
+        .file_stem()
+        .filter(|_| input != "-")
+        .map_or_else(|| "part".to_owned(), |s| s.to_string_lossy().into_owned());
+    let header = if records.has_header {
+        csv_line(&records.headers)?
+    } else {
+        String::new()
+    };
+    fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("failed to create {}", dir.display()))?;
+    let staging = tempfile::Builder::new()
+        .prefix(".rcli-split-")
+        .tempdir_in(dir)
+        .with_context(|| {
+            format!(
+                "failed to create a temporary directory in {}",
+                dir.display()
+            )
+        })?;
+
+    let mut split = Split {
+        dir: staging.path(),
+        template,
+        stem,
+        header,
+        parts: Vec::new(),
+    };
+    match &opts.by {
+        SplitBy::Column(column) => {
+            let column = resolve(&records.headers, column)?;
+            split.by_value(&mut records, column).await?;
+        }
+        &SplitBy::Rows(rows) => {
+            split
+                .in_order(&mut records, |part| part.rows >= rows)
+                .await?;
+        }
+        &SplitBy::Bytes(bytes) => {
+            split
+                .in_order(&mut records, |part| part.bytes + part.next > bytes)
+                .await?;
+        }
+    }
+
+    let mut parts: Vec<SplitPart> = split.parts.into_iter().map(|(part, _)| part).collect();
+    let targets: Vec<PathBuf> = parts
+        .iter()
+        .map(|part| dir.join(part.path.file_name().unwrap_or_default()))
+        .collect();
+    if !opts.force {
+        for target in &targets {
+            if fs::try_exists(target).await? {
+                bail!(
+                    "{} already exists; pass --force to overwrite it",
+                    target.display()
+                );
+            }
+        }
+    }
+    for (part, target) in parts.iter_mut().zip(targets) {
+        fs::rename(&part.path, &target)
+            .await
+            .with_context(|| format!("failed to move {} into place", target.display()))?;
+        part.path = target;
+    }
+    Ok(parts)
+}
+
+#[derive(Debug)]
+struct Split<'a> {
+    dir: &'a Path,
+    template: Template,
+    stem: String,
+    /// 每份开头写的表头行；输入没有表头时为空串
+    header: String,
+    /// 写出的各份和它们的字节数
+    parts: Vec<(SplitPart, u64)>,
+}
+
+/// 决定要不要另起一份时看的东西。
+#[derive(Debug, Clone, Copy)]
+struct Filled {
+    rows: usize,
+    bytes: u64,
+    /// 下一行有多少字节
+    next: u64,
+}
+
+impl Split<'_> {
+    /// 按行数或字节切：`full` 说当前这份装不下下一行了就另起一份，空的份总能装下一行。
+    async fn in_order(
+        &mut self,
+        records: &mut CsvRecords,
+        full: impl Fn(Filled) -> bool,
+    ) -> Result<()> {
+        let mut writer: Option<BufWriter<File>> = None;
+        while let Some(record) = records.next().await {
+            let line = csv_line(&record?)?;
+            let start = match (&writer, self.parts.last()) {
+                (Some(_), Some((part, bytes))) => full(Filled {
+                    rows: part.rows,
+                    bytes: *bytes,
+                    next: line.len() as u64,
+                }),
+                _ => true,
+            };
+            if start {
+                if let Some(mut done) = writer.take() {
+                    done.flush().await?;
+                }
+                let n = self.parts.len() + 1;
+                writer = Some(
+                    self.create(self.template.render(&self.stem, n, None))
+                        .await?,
+                );
+            }
+            let Some(out) = writer.as_mut() else {
+                unreachable!("a part is always open after the check above");
+            };
+            self.append(out, self.parts.len() - 1, &line).await?;
+        }
+        if let Some(mut done) = writer {
+            done.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// 按列值切：每个值一份，按值第一次出现的顺序编号；新值来了新建文件，之前见过的值追加到它那份。
+    async fn by_value(&mut self, records: &mut CsvRecords, column: usize) -> Result<()> {
+        let mut index: HashMap<String, usize> = HashMap::new();
+        // 小写的文件名 → 值；不同的值换掉非法字符后可能撞上同一个文件名，
+        // 大小写不敏感的文件系统上 `Juve` 和 `juve` 也是同一个文件
+        let mut names: HashMap<String, String> = HashMap::new();
+        let mut open: HashMap<usize, BufWriter<File>> = HashMap::new();
+        while let Some(record) = records.next().await {
+            let record = record?;
+            let value = record.get(column).unwrap_or_default();
+            let known = index.get(value).copied();
+            if !known.is_some_and(|i| open.contains_key(&i)) && open.len() >= MAX_OPEN_FILES {
+                for (_, mut writer) in open.drain() {
+                    writer.flush().await?;
+                }
+            }
+            let i = if let Some(i) = known {
+                i
+            } else {
+                let n = self.parts.len() + 1;
+                let name = self.template.render(&self.stem, n, Some(value));
+                if let Some(other) = names.insert(name.to_lowercase(), value.to_owned()) {
+                    bail!(
+                        "values {other:?} and {value:?} both map to file {name:?}; put {{n}} in \
+                         --name to tell them apart"
+                    );
+                }
+                let writer = self.create(name).await?;
+                index.insert(value.to_owned(), n - 1);
+                open.insert(n - 1, writer);
+                n - 1
+            };
+            let writer = match open.entry(i) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let file = OpenOptions::new()
+                        .append(true)
+                        .open(&self.parts[i].0.path)
+                        .await?;
+                    entry.insert(BufWriter::new(file))
+                }
+            };
+            let line = csv_line(&record)?;
+            self.append(writer, i, &line).await?;
+        }
+        for (_, mut writer) in open {
+            writer.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// 新建一份并写好表头。
+    async fn create(&mut self, name: String) -> Result<BufWriter<File>> {
+        let path = self.dir.join(name);
+        let file = File::create(&path)
+            .await
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(self.header.as_bytes()).await?;
+        self.parts
+            .push((SplitPart { path, rows: 0 }, self.header.len() as u64));
+        Ok(writer)
+    }
+
+    async fn append(&mut self, writer: &mut BufWriter<File>, i: usize, line: &str) -> Result<()> {
+        writer.write_all(line.as_bytes()).await?;
+        let (part, bytes) = &mut self.parts[i];
+        part.rows += 1;
+        *bytes += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// 解析好的文件名模板，里面可以用：
+///
+/// - `{stem}`：输入文件名去掉扩展名，读标准输入时是 `part`
+/// - `{n}`：第几份，从 1 开始；`{n:04}` 补零到 4 位
+/// - `{value}`：按列值分时这一行的值，见 [`file_safe`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Template(Vec<Piece>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Stem,
+    /// 补零到多少位
+    Number(usize),
+    Value,
+}
+
+impl Template {
+    fn parse(src: Option<&str>, by: &SplitBy) -> Result<Self> {
+        let by_value = matches!(by, SplitBy::Column(_));
+        let src = match src {
+            Some(src) => src,
+            None if by_value => "{stem}-{value}.csv",
+            None => "{stem}-{n}.csv",
+        };
+        if src.contains(['/', '\\']) {
+            bail!("--name {src:?} is a file name template, not a path");
+        }
+        let mut pieces = Vec::new();
+        let mut rest = src;
+        while let Some(open) = rest.find(['{', '}']) {
+            if rest[open..].starts_with('}') {
+                bail!("unmatched '}}' in --name {src:?}");
+            }
+            if open > 0 {
+                pieces.push(Piece::Text(rest[..open].to_owned()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .with_context(|| format!("unclosed '{{' in --name {src:?}"))?;
+            pieces.push(match &rest[open + 1..open + close] {
+                "stem" => Piece::Stem,
+                "value" if by_value => Piece::Value,
+                "value" => bail!("{{value}} in --name only applies to --by"),
+                "n" => Piece::Number(0),
+                spec => match spec.strip_prefix("n:").map(str::parse) {
+                    Some(Ok(width)) => Piece::Number(width),
+                    _ => bail!(
+                        "unknown placeholder {{{spec}}} in --name, expected {{stem}}, {{n}}, \
+                         {{n:04}} or {{value}}"
+                    ),
+                },
+            });
+            rest = &rest[open + close + 1..];
+        }
+        if !rest.is_empty() {
+            pieces.push(Piece::Text(rest.to_owned()));
+        }
+
+        let has = |f: fn(&Piece) -> bool| pieces.iter().any(f);
+        let numbered = has(|p| matches!(p, Piece::Number(_)));
+        if !(numbered || by_value && has(|p| *p == Piece::Value)) {
+            let needed = if by_value { "{n} or {value}" } else { "{n}" };
+            bail!("--name {src:?} gives every part the same file name; add {needed}");
+        }
+        Ok(Self(pieces))
+    }
+
+    fn render(&self, stem: &str, n: usize, value: Option<&str>) -> String {
+        self.0
+            .iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => text.clone(),
+                Piece::Stem => stem.to_owned(),
+                Piece::Number(width) => format!("{n:0width$}"),
+                Piece::Value => file_safe(value.unwrap_or_default()),
+            })
+            .collect()
+    }
+}
+
+/// 列值放进文件名：路径分隔符、Windows 不允许的字符和控制字符换成 `_`，空值写成 `empty`。
+fn file_safe(value: &str) -> String {
+    if value.is_empty() {
+        return "empty".to_owned();
+    }
+    let safe: String = value
+        .chars()
+        .map(|c| {
+            if c.is_control() || r#"/\:*?"<>|"#.contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    // `.` 和 `..` 不是文件名
+    if safe.chars().all(|c| c == '.') {
+        safe.replace('.', "_")
+    } else {
+        safe
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template() -> Result<()> {
+        let rows = SplitBy::Rows(10);
+        let by = SplitBy::Column("Nationality".to_owned());
+        assert_eq!(
+            Template::parse(None, &rows)?.render("juventus", 3, None),
+            "juventus-3.csv"
+        );
+        assert_eq!(
+            Template::parse(Some("{stem}_{n:03}.csv"), &rows)?.render("juventus", 7, None),
+            "juventus_007.csv"
+        );
+        assert_eq!(
+            Template::parse(None, &by)?.render("juventus", 2, Some("Bosnia/Herzegovina")),
+            "juventus-Bosnia_Herzegovina.csv"
+        );
+        assert_eq!(
+            Template::parse(Some("{value}.csv"), &by)?.render("x", 1, Some("")),
+            "empty.csv"
+        );
+
+        // 每份都叫同一个名字、{value} 用在按行切上、占位符写错、带路径，都报错
+        assert!(Template::parse(Some("part.csv"), &rows).is_err());
+        assert!(Template::parse(Some("{value}.csv"), &rows).is_err());
+        assert!(Template::parse(Some("{N}.csv"), &rows).is_err());
+        assert!(Template::parse(Some("{n.csv"), &rows).is_err());
+        assert!(Template::parse(Some("out/{n}.csv"), &rows).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_split_by_rows_bytes_and_value() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("squad.csv");
+        fs::write(&input, "name,team\na,Juve\nb,Genoa\nc,Juve\nd,\ne,Juve\n").await?;
+        let input = input.to_str().context("utf-8 path")?;
+        let split = |by, out: &str| {
+            let out = dir.path().join(out);
+            async move {
+                let opts = CsvSplitOptions {
+                    read: CsvReadOptions::default(),
+                    by,
+                    name: None,
+                    force: false,
+                };
+                let parts = process_csv_split(input, &out, &opts).await?;
+                let mut files = Vec::new();
+                for part in parts {
+                    let name = part.path.file_name().context("file name")?;
+                    let text = fs::read_to_string(&part.path).await?;
+                    files.push((name.to_string_lossy().into_owned(), part.rows, text));
+                }
+                anyhow::Ok(files)
+            }
+        };
+
+        let parts = split(SplitBy::Rows(2), "rows").await?;
+        assert_eq!(
+            parts,
+            [
+                (
+                    "squad-1.csv".to_owned(),
+                    2,
+                    "name,team\na,Juve\nb,Genoa\n".to_owned()
+                ),
+                (
+                    "squad-2.csv".to_owned(),
+                    2,
+                    "name,team\nc,Juve\nd,\n".to_owned()
+                ),
+                (
+                    "squad-3.csv".to_owned(),
+                    1,
+                    "name,team\ne,Juve\n".to_owned()
+                ),
+            ]
+        );
+
+        // 表头 10 字节，数据行 3 到 8 字节
+        let parts = split(SplitBy::Bytes(25), "bytes").await?;
+        let rows: Vec<usize> = parts.iter().map(|(_, rows, _)| *rows).collect();
+        assert_eq!(rows, [2, 2, 1]);
+        assert!(parts.iter().all(|(_, _, text)| text.len() <= 25));
+        // 一行都装不下时每行自成一份
+        assert_eq!(split(SplitBy::Bytes(1), "tiny").await?.len(), 5);
+
+        let parts = split(SplitBy::Column("team".to_owned()), "team").await?;
+        assert_eq!(
+            parts,
+            [
+                (
+                    "squad-Juve.csv".to_owned(),
+                    3,
+                    "name,team\na,Juve\nc,Juve\ne,Juve\n".to_owned()
+                ),
+                (
+                    "squad-Genoa.csv".to_owned(),
+                    1,
+                    "name,team\nb,Genoa\n".to_owned()
+                ),
+                (
+                    "squad-empty.csv".to_owned(),
+                    1,
+                    "name,team\nd,\n".to_owned()
+                ),
+            ]
+        );
+
+        // 已有的文件不覆盖，加上 force 才行
+        let err = split(SplitBy::Column("team".to_owned()), "team")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("squad-Juve.csv already exists"));
+        let opts = CsvSplitOptions {
+            read: CsvReadOptions::default(),
+            by: SplitBy::Column("team".to_owned()),
+            name: None,
+            force: true,
+        };
+        let parts = process_csv_split(input, &dir.path().join("team"), &opts).await?;
+        assert_eq!(parts.len(), 3);
+
+        let mixed = dir.path().join("mixed.csv");
+        fs::write(&mixed, "name,team\na,Juve\nb,Genoa\nc,juve\n").await?;
+        let out = dir.path().join("mixed");
+        let err = process_csv_split(mixed.to_str().context("utf-8 path")?, &out, &opts)
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains(r#"values "Juve" and "juve" both map to file"#)
+        );
+        // 出错时一份都不留
+        assert!(fs::read_dir(&out).await?.next_entry().await?.is_none());
+        Ok(())
+    }
+}
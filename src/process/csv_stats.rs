@@ -0,0 +1,549 @@
+//! `csv stats`：不用打开表格就能数清楚的汇总，整列的或者按 `--group-by` 分组的。
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Result, bail};
+use serde_json::{Map, Number, Value, json};
+
+use crate::{
+    cli::OutputFormat,
+    process::{
+        csv_columns::resolve,
+        csv_convert::{CsvRecords, RecordWriter},
+        csv_dialect::CsvReadOptions,
+        csv_filter::Filter,
+        csv_infer::{ColumnType, parse_date},
+    },
+};
+
+/// [`process_csv_stats`] 的选项。
+#[derive(Debug, Clone, Default)]
+pub struct CsvStatsOptions {
+    pub read: CsvReadOptions,
+    /// 分组列，列名或从 1 开始的序号；空表示不分组，按列汇总
+    pub group_by: Vec<String>,
+    /// 每组要算的聚合，如 `count`、`min(DOB)`，写法原样当输出的键名；分组时为空就只数行数
+    pub agg: Vec<String>,
+    /// `--where` 表达式，只统计满足条件的行
+    pub filter: Option<String>,
+}
+
+/// 统计完整个输入后，按 `format` 序列化返回。
+///
+/// 不分组时每列一条记录：非空个数、空值个数、不同值个数、最小 / 最大值、平均值。分组时每组一条，
+/// 按首次出现的顺序输出：分组列的值加上每一项聚合。内存随组数和不同值个数增长，和行数无关。
+///
+/// # Errors
+pub async fn process_csv_stats(
+    input: &str,
+    format: OutputFormat,
+    opts: &CsvStatsOptions,
+) -> Result<String> {
+    if format.is_binary() {
+        bail!("stats are returned as text; {format} output is not supported");
+    }
+    let mut records = CsvRecords::open(input, &opts.read).await?;
+    let headers = records.headers.clone();
+    let filter = opts
+        .filter
+        .as_deref()
+        .map(|src| Filter::parse(src, &headers))
+        .transpose()?;
+
+    let keys = opts
+        .group_by
+        .iter()
+        .map(|column| resolve(&headers, column))
+        .collect::<Result<Vec<_>>>()?;
+    let aggs = aggs(&opts.agg, &headers, &keys)?;
+
+    // 不分组时所有行落在同一个空键下，每列都统计
+    let columns: Vec<usize> = if keys.is_empty() {
+        (0..headers.len()).collect()
+    } else {
+        let mut columns = Vec::new();
+        for column in aggs.iter().filter_map(|agg| agg.column) {
+            if !columns.contains(&column) {
+                columns.push(column);
+            }
+        }
+        columns
+    };
+    let distinct: Vec<bool> = columns
+        .iter()
+        .map(|c| {
+            keys.is_empty()
+                || aggs
+                    .iter()
+                    .any(|agg| agg.kind == AggKind::Distinct && agg.column == Some(*c))
+        })
+        .collect();
+
+    let mut order: Vec<Vec<String>> = Vec::new();
+    let mut groups: HashMap<Vec<String>, Group> = HashMap::new();
+    while let Some(record) = records.next().await {
+        let record = record?;
+        if filter.as_ref().is_some_and(|f| !f.matches(&record)) {
+            continue;
+        }
+        let key: Vec<String> = keys
+            .iter()
+            .map(|i| record.get(*i).unwrap_or_default().to_owned())
+            .collect();
+        let group = groups.entry(key).or_insert_with_key(|key| {
+            order.push(key.clone());
+            Group::new(&distinct)
+        });
+        group.rows += 1;
+        for (stats, i) in group.columns.iter_mut().zip(&columns) {
+            stats.push(record.get(*i).unwrap_or_default());
+        }
+    }
+
+    let mut writer = RecordWriter::new(Vec::new(), format).with_toml_root("stats".to_owned());
+    if keys.is_empty() {
+        let group = groups
+            .remove(&Vec::new())
+            .unwrap_or_else(|| Group::new(&distinct));
+        for (header, stats) in headers.iter().zip(&group.columns) {
+            writer.write(&stats.summary(header)).await?;
+        }
+    } else {
+        for key in order {
+            let Some(group) = groups.remove(&key) else {
+                continue;
+            };
+            let mut row = Map::new();
+            for (i, value) in keys.iter().zip(key) {
+                row.insert(headers[*i].clone(), Value::String(value));
+            }
+            for agg in &aggs {
+                let stats = agg
+                    .column
+                    .and_then(|c| columns.iter().position(|x| *x == c))
+                    .map(|i| &group.columns[i]);
+                row.insert(agg.label.clone(), agg.value(group.rows, stats));
+            }
+            writer.write(&Value::Object(row)).await?;
+        }
+    }
+    Ok(String::from_utf8(writer.finish().await?)?)
+}
+
+#[derive(Debug)]
+struct Group {
+    rows: usize,
+    /// 和 `columns` 一一对应
+    columns: Vec<ColumnStats>,
+}
+
+impl Group {
+    fn new(distinct: &[bool]) -> Self {
+        Self {
+            rows: 0,
+            columns: distinct.iter().map(|d| ColumnStats::new(*d)).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AggKind {
+    /// `count` 数组内行数，`count(col)` 数该列非空的个数
+    Count,
+    Nulls,
+    /// 不同值的个数
+    Distinct,
+    /// 整列都是数字时按数值比，整列都是日期时按日期比，否则按字符串比
+    Min,
+    Max,
+    /// 只算数字；列里混了非数字时为 `null`
+    Sum,
+    Mean,
+}
+
+/// 解析 `--agg`；不分组时不能给，分组却没给时默认只数行数。
+fn aggs(specs: &[String], headers: &[String], keys: &[usize]) -> Result<Vec<Agg>> {
+    if keys.is_empty() {
+        if !specs.is_empty() {
+            bail!("--agg needs --group-by");
+        }
+        return Ok(Vec::new());
+    }
+    if specs.is_empty() {
+        return Ok(vec![Agg::parse("count", headers)?]);
+    }
+    let aggs: Vec<Agg> = specs
+        .iter()
+        .map(|agg| Agg::parse(agg, headers))
+        .collect::<Result<_>>()?;
+    // 输出的每一列名字都得不一样，不然后面的会悄悄盖掉前面的
+    let mut labels: HashSet<&str> = keys.iter().map(|&i| headers[i].as_str()).collect();
+    for agg in &aggs {
+        if !labels.insert(&agg.label) {
+            bail!(
+                "--agg {:?} is given twice or clashes with a --group-by column",
+                agg.label
+            );
+        }
+    }
+    Ok(aggs)
+}
+
+#[derive(Debug)]
+struct Agg {
+    kind: AggKind,
+    /// 源列下标；只有不带列的 `count` 是 `None`
+    column: Option<usize>,
+    label: String,
+}
+
+impl Agg {
+    fn parse(src: &str, headers: &[String]) -> Result<Self> {
+        let label = src.trim().to_owned();
+        let (name, column) = match label.split_once('(') {
+            Some((name, rest)) => {
+                let Some(column) = rest.strip_suffix(')') else {
+                    bail!("invalid aggregate {label:?}, expected name(column)");
+                };
+                (name.trim(), Some(resolve(headers, column.trim())?))
+            }
+            None => (label.as_str(), None),
+        };
+        let kind = match name.to_lowercase().as_str() {
+            "count" => AggKind::Count,
+            "nulls" => AggKind::Nulls,
+            "distinct" => AggKind::Distinct,
+            "min" => AggKind::Min,
+            "max" => AggKind::Max,
+            "sum" => AggKind::Sum,
+            "mean" | "avg" => AggKind::Mean,
+            _ => bail!(
+                "unknown aggregate {name:?}, expected count, nulls, distinct, min, max, sum or \
+                 mean"
+            ),
+        };
+        if column.is_none() && kind != AggKind::Count {
+            bail!("aggregate {label:?} needs a column, e.g. {name}(Kit Number)");
+        }
+        Ok(Self {
+            kind,
+            column,
+            label,
+        })
+    }
+
+    fn value(&self, rows: usize, stats: Option<&ColumnStats>) -> Value {
+        let Some(stats) = stats else {
+            return Value::from(rows);
+        };
+        match self.kind {
+            AggKind::Count => Value::from(stats.count),
+            AggKind::Nulls => Value::from(stats.nulls),
+            AggKind::Distinct => Value::from(stats.distinct.len()),
+            AggKind::Min => stats.min(),
+            AggKind::Max => stats.max(),
+            AggKind::Sum => stats.sum(),
+            AggKind::Mean => stats.mean(),
+        }
+    }
+}
+
+/// 日期比大小用的键：年、月、日，带时间的再加时、分、秒、纳秒。
+type DateKey = (i64, u8, u8, u8, u8, u8, u32);
+
+/// 一列（或一组里的一列）的累计值。数字、日期和字符串的最值分开记，最后看整列是什么再挑。
+#[derive(Debug)]
+struct ColumnStats {
+    count: usize,
+    nulls: usize,
+    /// 只有要输出不同值个数时才记，免得大列白占内存
+    track_distinct: bool,
+    distinct: HashSet<String>,
+    /// 目前为止的非空值是否都是数字
+    numeric: bool,
+    sum: f64,
+    /// （数值，原文）；输出时用原文，`10` 不会变成 `10.0`
+    min_num: Option<(f64, String)>,
+    max_num: Option<(f64, String)>,
+    /// 目前为止的非空值是否都是日期
+    dated: bool,
+    min_date: Option<(DateKey, String)>,
+    max_date: Option<(DateKey, String)>,
+    min_str: Option<String>,
+    max_str: Option<String>,
+}
+
+impl ColumnStats {
+    fn new(track_distinct: bool) -> Self {
+        Self {
+            count: 0,
+            nulls: 0,
+            track_distinct,
+            distinct: HashSet::new(),
+            numeric: true,
+            sum: 0.0,
+            min_num: None,
+            max_num: None,
+            dated: true,
+            min_date: None,
+            max_date: None,
+            min_str: None,
+            max_str: None,
+        }
+    }
+
+    fn push(&mut self, raw: &str) {
+        if raw.is_empty() {
+            self.nulls += 1;
+            return;
+        }
+        self.count += 1;
+        if self.track_distinct && !self.distinct.contains(raw) {
+            self.distinct.insert(raw.to_owned());
+        }
+        if self.min_str.as_deref().is_none_or(|m| raw < m) {
+            self.min_str = Some(raw.to_owned());
+        }
+        if self.max_str.as_deref().is_none_or(|m| raw > m) {
+            self.max_str = Some(raw.to_owned());
+        }
+        if self.dated {
+            match date_key(raw) {
+                Some(key) => {
+                    if self.min_date.as_ref().is_none_or(|(m, _)| key < *m) {
+                        self.min_date = Some((key, raw.to_owned()));
+                    }
+                    if self.max_date.as_ref().is_none_or(|(m, _)| key > *m) {
+                        self.max_date = Some((key, raw.to_owned()));
+                    }
+                }
+                None => self.dated = false,
+            }
+        }
+        if !self.numeric {
+            return;
+        }
+        match raw.trim().parse::<f64>().ok().filter(|n| n.is_finite()) {
+            Some(n) => {
+                self.sum += n;
+                if self.min_num.as_ref().is_none_or(|(m, _)| n < *m) {
+                    self.min_num = Some((n, raw.to_owned()));
+                }
+                if self.max_num.as_ref().is_none_or(|(m, _)| n > *m) {
+                    self.max_num = Some((n, raw.to_owned()));
+                }
+            }
+            None => self.numeric = false,
+        }
+    }
+
+    /// 不分组时一列一条的汇总记录。
+    fn summary(&self, column: &str) -> Value {
+        json!({
+            "column": column,
+            "count": self.count,
+            "nulls": self.nulls,
+            "distinct": self.distinct.len(),
+            "min": self.min(),
+            "max": self.max(),
+            "mean": self.mean(),
+        })
+    }
+
+    fn min(&self) -> Value {
+        let date = self.min_date.as_ref().map(|(_, raw)| raw.as_str());
+        self.extreme(self.min_num.as_ref(), date, self.min_str.as_deref())
+    }
+
+    fn max(&self) -> Value {
+        let date = self.max_date.as_ref().map(|(_, raw)| raw.as_str());
+        self.extreme(self.max_num.as_ref(), date, self.max_str.as_deref())
+    }
+
+    fn extreme(
+        &self,
+        num: Option<&(f64, String)>,
+        date: Option<&str>,
+        text: Option<&str>,
+    ) -> Value {
+        match (self.numeric, num, self.dated, date, text) {
+            (true, Some((n, raw)), ..) => match ColumnType::Integer.parse(raw.trim()) {
+                Value::Number(int) => Value::Number(int),
+                _ => Number::from_f64(*n).map_or(Value::Null, Value::Number),
+            },
+            (_, _, true, Some(date), _) => Value::from(date),
+            (.., Some(text)) => Value::from(text),
+            _ => Value::Null,
+        }
+    }
+
+    fn sum(&self) -> Value {
+        if self.numeric && self.count > 0 {
+            Number::from_f64(self.sum).map_or(Value::Null, Value::Number)
+        } else {
+            Value::Null
+        }
+    }
+
+    #[expect(clippy::cast_precision_loss, reason = "行数远小于 2^52")]
+    fn mean(&self) -> Value {
+        if self.numeric && self.count > 0 {
+            Number::from_f64(self.sum / self.count as f64).map_or(Value::Null, Value::Number)
+        } else {
+            Value::Null
+        }
+    }
+}
+
+/// 日期认 ISO 8601（`2024-01-31`、`2024-01-31T08:00:00Z`）和 `Apr 18, 1990` 这种英文月份写法，
+/// 后者日期后面可以跟别的字，如 `Apr 18, 1990 (29)`。认不出是日期时返回 `None`。
+fn date_key(raw: &str) -> Option<DateKey> {
+    let raw = raw.trim();
+    if let Some(dt) = parse_date(raw) {
+        let date = dt.date?;
+        let time = dt.time.unwrap_or(toml::value::Time {
+            hour: 0,
+            minute: 0,
+            second: Some(0),
+            nanosecond: None,
+        });
+        return Some((
+            i64::from(date.year),
+            date.month,
+            date.day,
+            time.hour,
+            time.minute,
+            time.second.unwrap_or(0),
+            time.nanosecond.unwrap_or(0),
+        ));
+    }
+    // `Apr 18, 1990`、`April 18, 1990 (29)`
+    let mut words = raw.split_whitespace();
+    let month = words.next()?.to_lowercase();
+    let month = MONTHS
+        .iter()
+        .position(|name| month.len() >= 3 && name.starts_with(month.as_str()))?;
+    let day: u8 = words.next()?.strip_suffix(',')?.parse().ok()?;
+    let year: i64 = words.next()?.parse().ok()?;
+    if !(1..=31).contains(&day) {
+        return None;
+    }
+    Some((year, u8::try_from(month + 1).ok()?, day, 0, 0, 0, 0))
+}
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stats_per_column() -> Result<()> {
+        let out = process_csv_stats(
+            "assets/juventus.csv",
+            OutputFormat::Ndjson,
+            &CsvStatsOptions::default(),
+        )
+        .await?;
+        let rows: Vec<Value> = out
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(rows.len(), 5);
+        assert_eq!(
+            rows[4],
+            json!({
+                "column": "Kit Number",
+                "count": 27,
+                "nulls": 0,
+                "distinct": 27,
+                "min": 1,
+                "max": 77,
+                "mean": 492.0 / 27.0,
+            })
+        );
+        // DOB 按日期比，不是按字母
+        assert_eq!(
+            (&rows[2]["min"], &rows[2]["max"]),
+            (&json!("Jan 28, 1978 (41)"), &json!("Aug 12, 1999 (20)"))
+        );
+        assert_eq!(rows[0]["mean"], Value::Null);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stats_group_by() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.csv");
+        tokio::fs::write(&input, "team,kit,dob\nA,10,1990\nB,3,\nA,9,1985\nA,,1999\n").await?;
+        let opts = CsvStatsOptions {
+            group_by: vec!["team".to_owned()],
+            agg: [
+                "count",
+                "count(kit)",
+                "min(kit)",
+                "max(3)",
+                "mean(kit)",
+                "nulls(dob)",
+            ]
+            .map(str::to_owned)
+            .to_vec(),
+            ..CsvStatsOptions::default()
+        };
+        let out = process_csv_stats(
+            input.to_str().context("utf-8 path")?,
+            OutputFormat::Ndjson,
+            &opts,
+        )
+        .await?;
+        assert_eq!(
+            out,
+            "{\"team\":\"A\",\"count\":3,\"count(kit)\":2,\"min(kit)\":9,\"max(3)\":1999,\"\
+             mean(kit)\":9.5,\"nulls(dob)\":0}\n{\"team\":\"B\",\"count\":1,\"count(kit)\":1,\"\
+             min(kit)\":3,\"max(3)\":null,\"mean(kit)\":3.0,\"nulls(dob)\":1}\n"
+        );
+
+        // 同一项写两遍会撞上同一个输出列
+        let opts = CsvStatsOptions {
+            group_by: vec!["team".to_owned()],
+            agg: vec!["min(kit)".to_owned(), "min(kit)".to_owned()],
+            ..CsvStatsOptions::default()
+        };
+        let err = process_csv_stats(
+            input.to_str().context("utf-8 path")?,
+            OutputFormat::Ndjson,
+            &opts,
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains(r#"--agg "min(kit)" is given twice"#)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_agg_parse_errors() {
+        let headers = ["a".to_owned()];
+        assert!(Agg::parse("min", &headers).is_err());
+        assert!(Agg::parse("median(a)", &headers).is_err());
+        assert!(Agg::parse("min(b)", &headers).is_err());
+        assert!(Agg::parse("min(a", &headers).is_err());
+    }
+}
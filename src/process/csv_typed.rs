@@ -0,0 +1,330 @@
+//! 给库用户的带类型 API：按 serde 把每行读成 `T`，或者把 `T` 写成各种格式。
+
+use std::{
+    error::Error,
+    fmt, future,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use csv::StringRecord;
+use futures_core::Stream;
+use serde::{Serialize, de::DeserializeOwned};
+use tokio::io::AsyncRead;
+
+use crate::{
+    cli::OutputFormat,
+    process::{
+        csv_convert::{CsvRecords, RecordWriter},
+        csv_dialect::CsvReadOptions,
+    },
+};
+
+/// 逐行读成 `T` 的记录流。既可以 `while let Some(row) = reader.next().await`，也实现了 [`Stream`]。
+///
+/// 读和命令行走同一套方言探测、转码和工作簿读取，见 [`CsvReadOptions`]。记录按表头名对应到
+/// 字段上，没有表头时列名是 `col1`、`col2`……，也可以读成元组按位置对应。
+#[derive(Debug)]
+pub struct CsvReader<T> {
+    records: CsvRecords,
+    headers: StringRecord,
+    /// 已经交出去的数据行数
+    row: usize,
+    _record: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> CsvReader<T> {
+    /// 读文件，`-` 表示标准输入。
+    ///
+    /// # Errors
+    ///
+    /// 打不开输入或读不出表头时返回 [`CsvError::Open`]。
+    pub async fn open(input: &str, opts: &CsvReadOptions) -> Result<Self, CsvError> {
+        let records = CsvRecords::open(input, opts)
+            .await
+            .map_err(CsvError::Open)?;
+        Ok(Self::new(records))
+    }
+
+    /// 从任意异步读取端读，比如网络流或内存里的 `&[u8]`。
+    ///
+    /// # Errors
+    ///
+    /// 读不出表头时返回 [`CsvError::Open`]。
+    pub async fn from_reader(
+        reader: impl AsyncRead + Unpin + Send + 'static,
+        opts: &CsvReadOptions,
+    ) -> Result<Self, CsvError> {
+        let records = CsvRecords::from_reader(reader, "<reader>", opts)
+            .await
+            .map_err(CsvError::Open)?;
+        Ok(Self::new(records))
+    }
+
+    fn new(records: CsvRecords) -> Self {
+        let headers = StringRecord::from(records.headers.clone());
+        Self {
+            records,
+            headers,
+            row: 0,
+            _record: PhantomData,
+        }
+    }
+
+    /// 列名；没有表头时是 `col1`、`col2`……
+    pub fn headers(&self) -> &[String] {
+        &self.records.headers
+    }
+
+    /// 下一行；读完返回 `None`。某一行出错不影响继续往下读。
+    pub async fn next(&mut self) -> Option<Result<T, CsvError>> {
+        future::poll_fn(|cx| self.poll_row(cx)).await
+    }
+
+    fn poll_row(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<T, CsvError>>> {
+        let Some(record) = std::task::ready!(self.records.poll_next(cx)) else {
+            return Poll::Ready(None);
+        };
+        self.row += 1;
+        let row = self.row;
+        let record = match record {
+            Ok(record) => record,
+            Err(source) => {
+                let line = source.position().map(csv::Position::line);
+                return Poll::Ready(Some(Err(CsvError::Read { row, line, source })));
+            }
+        };
+        let line = record.position().map(csv::Position::line);
+        Poll::Ready(Some(record.deserialize(Some(&self.headers)).map_err(|e| {
+            let (column, message) = match e.kind() {
+                csv::ErrorKind::Deserialize { err, .. } => (
+                    err.field()
+                        .and_then(|i| self.headers.get(usize::try_from(i).ok()?))
+                        .map(str::to_owned),
+                    err.kind().to_string(),
+                ),
+                kind => (None, format!("{kind:?}")),
+            };
+            CsvError::Deserialize {
+                row,
+                line,
+                column,
+                message,
+            }
+        })))
+    }
+}
+
+impl<T: DeserializeOwned> Stream for CsvReader<T> {
+    type Item = Result<T, CsvError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_row(cx)
+    }
+}
+
+/// 把 `records` 逐条写成 `format`，写法和 `csv` 转换的输出一样；写完 flush 之后交还 `writer`。
+///
+/// CSV 的表头取第一条记录的字段名；TOML 的表数组挂在 `records` 下面。
+///
+/// # Errors
+///
+/// 某条记录序列化或写出失败时返回带行号的 [`CsvError::Write`]。
+pub async fn write_records<W, T>(
+    writer: W,
+    format: OutputFormat,
+    records: impl IntoIterator<Item = T>,
+) -> Result<W, CsvError>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let mut writer = RecordWriter::new(writer, format);
+    for (i, record) in records.into_iter().enumerate() {
+        let written = match serde_json::to_value(record) {
+            Ok(value) => writer.write(&value).await,
+            Err(e) => Err(e.into()),
+        };
+        written.map_err(|source| CsvError::Write {
+            row: Some(i + 1),
+            source,
+        })?;
+    }
+    writer
+        .finish()
+        .await
+        .map_err(|source| CsvError::Write { row: None, source })
+}
+
+/// [`write_records`] 写进内存，作为字符串返回；二进制格式请用 [`write_records`] 写进 `Vec<u8>`。
+///
+/// # Errors
+///
+/// 同 [`write_records`]；`format` 是 Parquet / Arrow / xlsx 时也返回错误。
+pub async fn records_to_string<T: Serialize>(
+    format: OutputFormat,
+    records: impl IntoIterator<Item = T>,
+) -> Result<String, CsvError> {
+    if format.is_binary() {
+        return Err(CsvError::Write {
+            row: None,
+            source: anyhow::anyhow!("{format} output is binary, not a string"),
+        });
+    }
+    let bytes = write_records(Vec::new(), format, records).await?;
+    String::from_utf8(bytes).map_err(|e| CsvError::Write {
+        row: None,
+        source: e.into(),
+    })
+}
+
+/// 带类型 API 的错误。和某一行有关的错误都带数据行号（不算表头，从 1 开始）。
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CsvError {
+    /// 打不开输入、方言或工作表不对、读不出表头，还没读到数据行
+    Open(anyhow::Error),
+    /// 这一行本身是坏的：列数不对、不是合法的 UTF-8、底层 I/O 出错
+    Read {
+        row: usize,
+        /// 文件里的物理行号，读的是工作簿或者出错时拿不到位置就是 `None`
+        line: Option<u64>,
+        source: csv::Error,
+    },
+    /// 这一行读出来了，但转不成目标类型
+    Deserialize {
+        row: usize,
+        line: Option<u64>,
+        /// 出错的列名，能定位到列时才有
+        column: Option<String>,
+        message: String,
+    },
+    /// 第 `row` 条记录（从 1 开始）序列化或写出失败；收尾时出错 `row` 为 `None`
+    Write {
+        row: Option<usize>,
+        source: anyhow::Error,
+    },
+}
+
+impl CsvError {
+    /// 和哪一行有关；读写之外的错误返回 `None`。
+    pub fn row(&self) -> Option<usize> {
+        match self {
+            Self::Read { row, .. } | Self::Deserialize { row, .. } => Some(*row),
+            Self::Write { row, .. } => *row,
+            Self::Open(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Open(_) => f.write_str("failed to open csv"),
+            Self::Read { row, line, .. } | Self::Deserialize { row, line, .. } => {
+                write!(f, "row {row}")?;
+                if let Some(line) = line {
+                    write!(f, " (line {line})")?;
+                }
+                match self {
+                    Self::Deserialize {
+                        column: Some(column),
+                        message,
+                        ..
+                    } => write!(f, ", column {column:?}: {message}"),
+                    Self::Deserialize { message, .. } => write!(f, ": {message}"),
+                    _ => f.write_str(": malformed csv"),
+                }
+            }
+            Self::Write { row: Some(row), .. } => write!(f, "failed to write record {row}"),
+            Self::Write { row: None, .. } => f.write_str("failed to write records"),
+        }
+    }
+}
+
+impl Error for CsvError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Open(e) | Self::Write { source: e, .. } => Some(e.as_ref()),
+            Self::Read { source, .. } => Some(source),
+            Self::Deserialize { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context as _;
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Player {
+        name: String,
+        position: String,
+        #[serde(rename = "DOB")]
+        dob: String,
+        nationality: String,
+        #[serde(rename = "Kit Number")]
+        kit: u8,
+    }
+
+    #[tokio::test]
+    async fn test_read_typed_then_write() -> anyhow::Result<()> {
+        let mut reader =
+            CsvReader::<Player>::open("assets/juventus.csv", &CsvReadOptions::default()).await?;
+        let mut players = Vec::new();
+        while let Some(player) = reader.next().await {
+            players.push(player?);
+        }
+        assert_eq!(players.len(), 27);
+        assert_eq!(players[2].name, "Gianluigi Buffon");
+        assert_eq!(players[2].kit, 77);
+
+        let csv = records_to_string(OutputFormat::Csv, &players[..1]).await?;
+        assert_eq!(
+            csv,
+            "Name,Position,DOB,Nationality,Kit Number\nWojciech Szczesny,Goalkeeper,\"Apr 18, \
+             1990 (29)\",Poland,1\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_errors_carry_row_numbers() -> anyhow::Result<()> {
+        let input: &[u8] = b"Name,Kit Number\nBuffon,77\n\"Perin\nMattia\",x\nChiellini\n";
+        let mut reader = CsvReader::<(String, u8)>::from_reader(
+            input,
+            &CsvReadOptions {
+                delimiter: Some(','),
+                has_header: Some(true),
+                ..CsvReadOptions::default()
+            },
+        )
+        .await?;
+        assert_eq!(
+            reader.next().await.transpose()?,
+            Some(("Buffon".to_owned(), 77))
+        );
+
+        // 单元格里有换行，物理行号和数据行号对不上
+        let err = reader.next().await.context("second row")?.unwrap_err();
+        assert!(matches!(
+            &err,
+            CsvError::Deserialize { row: 2, line: Some(3), column: Some(c), .. } if c == "Kit Number"
+        ));
+        assert!(
+            err.to_string()
+                .starts_with("row 2 (line 3), column \"Kit Number\": ")
+        );
+
+        // 列数不够也是这一行的错，照样带行号
+        let err = reader.next().await.context("third row")?.unwrap_err();
+        assert_eq!(err.row(), Some(3));
+        assert!(reader.next().await.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,342 @@
+//! `csv validate`：对照一份约定检查合作方发来的 CSV，入库之前把问题一次性列全。
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::process::{
+    csv_convert::CsvRecords,
+    csv_dialect::CsvReadOptions,
+    csv_infer::{ColumnType, read_spec},
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Schema {
+    /// 不允许 schema 里没写的列
+    #[serde(default)]
+    strict: bool,
+    columns: BTreeMap<String, ColumnRule>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ColumnRule {
+    /// 列必须存在
+    #[serde(default = "yes")]
+    required: bool,
+    /// 允许空单元格；空单元格只受这一条约束，不再检查类型、正则和可选值
+    #[serde(default = "yes")]
+    nullable: bool,
+    #[serde(rename = "type")]
+    column_type: Option<ColumnType>,
+    /// 整格匹配，不用自己写 `^` `$`
+    pattern: Option<String>,
+    #[serde(default)]
+    unique: bool,
+    #[serde(alias = "enum")]
+    allowed: Option<Vec<String>>,
+}
+
+fn yes() -> bool {
+    true
+}
+
+/// 校验结果。`violations` 为空就是通过。
+#[derive(Debug, Serialize)]
+pub struct CsvValidationReport {
+    pub valid: bool,
+    /// 检查过的数据行数
+    pub rows: usize,
+    pub violations: Vec<Violation>,
+}
+
+/// 一条违规。整列的问题（缺列、多列）没有行号。
+#[derive(Debug, Serialize)]
+pub struct Violation {
+    /// 数据行号，不算表头，从 1 开始
+    pub row: Option<usize>,
+    /// 文件里的物理行号，单元格里有换行时和 `row` 对不上
+    pub line: Option<u64>,
+    /// 列号，从 1 开始；schema 要求了但文件里没有的列为 `None`
+    pub column: Option<usize>,
+    pub name: String,
+    pub value: Option<String>,
+    /// 违反了哪一条：`required`、`strict`、`nullable`、`type`、`pattern`、`unique`、`allowed`
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // xlsx 这类输入没有物理行号，数据行号照样给
+        match (self.row, self.line) {
+            (Some(row), Some(line)) => write!(f, "row {row} (line {line}), ")?,
+            (Some(row), None) => write!(f, "row {row}, ")?,
+            (None, _) => {}
+        }
+        match self.column {
+            Some(column) => write!(f, "column {column} {:?}", self.name)?,
+            None => write!(f, "column {:?}", self.name)?,
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// 已经对上表头的一列规则。
+#[derive(Debug)]
+struct Check<'a> {
+    index: usize,
+    name: &'a str,
+    rule: &'a ColumnRule,
+    pattern: Option<Regex>,
+    /// `unique` 列：值 → 第一次出现的数据行号
+    seen: HashMap<String, usize>,
+}
+
+/// `schema` 是 JSON / YAML / TOML 文件，格式看扩展名：
+///
+/// ```yaml
+/// strict: true              # 不允许 schema 里没写的列，默认 false
+/// columns:
+///   Name: { nullable: false, unique: true }
+///   Position: { allowed: [Goalkeeper, Centre-Back] }
+///   Kit Number: { type: integer, pattern: '[1-9][0-9]?' }
+///   Agent: { required: false }
+/// ```
+///
+/// # Errors
+///
+/// 读不了输入或 schema 时返回错误；数据本身的问题都记在报告里。
+pub async fn process_csv_validate(
+    input: &str,
+    schema: &str,
+    read: &CsvReadOptions,
+) -> Result<CsvValidationReport> {
+    let spec: Schema = read_spec(schema).await?;
+    let mut records = CsvRecords::open(input, read).await?;
+    let headers = records.headers.clone();
+    let mut violations = Vec::new();
+
+    let mut checks = Vec::new();
+    for (name, rule) in &spec.columns {
+        let Some(index) = headers.iter().position(|h| h == name) else {
+            if rule.required {
+                violations.push(Violation {
+                    row: None,
+                    line: None,
+                    column: None,
+                    name: name.clone(),
+                    value: None,
+                    rule: "required",
+                    message: "required column is missing".to_owned(),
+                });
+            }
+            continue;
+        };
+        let pattern = rule
+            .pattern
+            .as_deref()
+            .map(|p| Regex::new(&format!("^(?:{p})$")))
+            .transpose()
+            .with_context(|| format!("invalid pattern for column {name:?}"))?;
+        checks.push(Check {
+            index,
+            name,
+            rule,
+            pattern,
+            seen: HashMap::new(),
+        });
+    }
+    checks.sort_by_key(|check| check.index);
+    if spec.strict {
+        for (i, name) in headers.iter().enumerate() {
+            if !spec.columns.contains_key(name) {
+                violations.push(Violation {
+                    row: None,
+                    line: None,
+                    column: Some(i + 1),
+                    name: name.clone(),
+                    value: None,
+                    rule: "strict",
+                    message: "column is not in the schema".to_owned(),
+                });
+            }
+        }
+    }
+
+    let mut rows = 0;
+    while let Some(record) = records.next().await {
+        let record = record?;
+        rows += 1;
+        let line = record.position().map(csv::Position::line);
+        for check in &mut checks {
+            let raw = record.get(check.index).unwrap_or_default();
+            let Some((rule, message)) = check.run(raw, rows) else {
+                continue;
+            };
+            violations.push(Violation {
+                row: Some(rows),
+                line,
+                column: Some(check.index + 1),
+                name: check.name.to_owned(),
+                value: Some(raw.to_owned()),
+                rule,
+                message,
+            });
+        }
+    }
+
+    Ok(CsvValidationReport {
+        valid: violations.is_empty(),
+        rows,
+        violations,
+    })
+}
+
+impl Check<'_> {
+    /// 一格只报第一条违反的规则，免得一个坏值刷出好几行。
+    fn run(&mut self, raw: &str, row: usize) -> Option<(&'static str, String)> {
+        if raw.is_empty() {
+            return (!self.rule.nullable).then(|| ("nullable", "value is empty".to_owned()));
+        }
+        if let Some(t) = self.rule.column_type
+            && !t.accepts(raw)
+        {
+            return Some(("type", format!("{raw:?} is not a valid {}", t.name())));
+        }
+        if let Some(pattern) = &self.pattern
+            && !pattern.is_match(raw)
+        {
+            return Some((
+                "pattern",
+                format!("{raw:?} does not match {}", pattern.as_str()),
+            ));
+        }
+        if let Some(allowed) = &self.rule.allowed
+            && !allowed.iter().any(|a| a == raw)
+        {
+            return Some(("allowed", format!("{raw:?} is not one of {allowed:?}")));
+        }
+        if self.rule.unique {
+            if let Some(first) = self.seen.get(raw) {
+                return Some(("unique", format!("{raw:?} already appears in row {first}")));
+            }
+            self.seen.insert(raw.to_owned(), row);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::fs;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_validate_reports_every_violation() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.csv");
+        let schema = dir.path().join("schema.yaml");
+        fs::write(
+            &input,
+            "Name,Position,Kit,Extra\nBuffon,Goalkeeper,1,x\n,Striker,abc,y\nBuffon,Goalkeeper,\
+             100,z\n",
+        )
+        .await?;
+        fs::write(
+            &schema,
+            "strict: true\ncolumns:\n\x20 Name: { nullable: false, unique: true }\n\x20 Position: \
+             { allowed: [Goalkeeper, Defender] }\n\x20 Kit: { type: integer, pattern: \
+             '[1-9][0-9]?' }\n\x20 Agent: {}\n",
+        )
+        .await?;
+
+        let report = process_csv_validate(
+            input.to_str().context("utf-8 path")?,
+            schema.to_str().context("utf-8 path")?,
+            &CsvReadOptions::default(),
+        )
+        .await?;
+        assert!(!report.valid);
+        assert_eq!(report.rows, 3);
+        let lines: Vec<String> = report.violations.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "column \"Agent\": required column is missing",
+                "column 4 \"Extra\": column is not in the schema",
+                "row 2 (line 3), column 1 \"Name\": value is empty",
+                "row 2 (line 3), column 2 \"Position\": \"Striker\" is not one of \
+                 [\"Goalkeeper\", \"Defender\"]",
+                "row 2 (line 3), column 3 \"Kit\": \"abc\" is not a valid integer",
+                "row 3 (line 4), column 1 \"Name\": \"Buffon\" already appears in row 1",
+                "row 3 (line 4), column 3 \"Kit\": \"100\" does not match ^(?:[1-9][0-9]?)$",
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_accepts_any_parsable_number() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("in.csv");
+        let schema = dir.path().join("schema.yaml");
+        fs::write(&input, "Kit,Rate\n007,+1.5\n+1,-2\n1.5,NaN\n").await?;
+        fs::write(
+            &schema,
+            "columns:\n\x20 Kit: { type: integer }\n\x20 Rate: { type: float }\n",
+        )
+        .await?;
+        let report = process_csv_validate(
+            input.to_str().context("utf-8 path")?,
+            schema.to_str().context("utf-8 path")?,
+            &CsvReadOptions::default(),
+        )
+        .await?;
+        let lines: Vec<String> = report.violations.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "row 3 (line 4), column 1 \"Kit\": \"1.5\" is not a valid integer",
+                "row 3 (line 4), column 2 \"Rate\": \"NaN\" is not a valid float",
+            ]
+        );
+
+        // 没有物理行号时仍然带数据行号
+        let violation = Violation {
+            line: None,
+            ..report
+                .violations
+                .into_iter()
+                .next()
+                .context("one violation")?
+        };
+        assert_eq!(
+            violation.to_string(),
+            "row 3, column 1 \"Kit\": \"1.5\" is not a valid integer"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_rejects_unknown_rule() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let schema = dir.path().join("schema.json");
+        fs::write(&schema, r#"{"columns": {"Name": {"uniq": true}}}"#).await?;
+        let result = process_csv_validate(
+            "assets/juventus.csv",
+            schema.to_str().context("utf-8 path")?,
+            &CsvReadOptions::default(),
+        )
+        .await;
+        assert!(result.is_err());
+        Ok(())
+    }
+}
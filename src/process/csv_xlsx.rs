@@ -0,0 +1,236 @@
+//! Excel 工作簿：读一个工作表当 CSV 输入，或者把结果写成 `.xlsx`。
+
+use std::io::Cursor;
+
+use anyhow::{Context, Result, bail};
+use calamine::{Data, Reader, open_workbook_auto_from_rs};
+use csv::StringRecord;
+use rust_xlsxwriter::{Format, Workbook, Worksheet};
+use serde_json::Value;
+
+use crate::process::{
+    csv_columnar::{days, micros},
+    csv_convert::{cell, keys},
+    csv_infer::parse_date,
+};
+
+/// 一个工作表最多多少行（含表头）。
+const MAX_ROWS: u32 = 1_048_576;
+
+/// 1970-01-01 在 Excel 里的序列号。
+const UNIX_EPOCH_SERIAL: i32 = 25_569;
+
+/// 开头几个字节像不像工作簿：zip（xlsx / xlsm / xlsb / ods）或 OLE 复合文档（老的
+/// xls），和扩展名无关。
+pub(crate) fn is_workbook(head: &[u8]) -> bool {
+    head.starts_with(b"PK\x03\x04") || head.starts_with(&[0xD0, 0xCF, 0x11, 0xE0])
+}
+
+/// 读出一个工作表，返回它的名字和所有行；第一行是不是表头由调用方决定。
+/// 单元格转成文本交给后面的流程，类型照旧由 `--infer` / `--schema` 决定。
+pub(crate) fn read_sheet(
+    bytes: Vec<u8>,
+    sheet: Option<&str>,
+) -> Result<(String, Vec<StringRecord>)> {
+    let mut workbook =
+        open_workbook_auto_from_rs(Cursor::new(bytes)).context("failed to open workbook")?;
+    let name = pick(&workbook.sheet_names(), sheet)?;
+    let range = workbook
+        .worksheet_range(&name)
+        .with_context(|| format!("failed to read worksheet {name:?}"))?;
+    let rows = range
+        .rows()
+        .map(|row| row.iter().map(text).collect())
+        .collect();
+    Ok((name, rows))
+}
+
+/// 没指定就是第一个工作表；先按名字找，找不到再当从 1 开始的序号。
+fn pick(names: &[String], sheet: Option<&str>) -> Result<String> {
+    let Some(sheet) = sheet else {
+        return names.first().cloned().context("workbook has no worksheets");
+    };
+    if let Some(name) = names.iter().find(|name| *name == sheet) {
+        return Ok(name.clone());
+    }
+    sheet
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| names.get(n.checked_sub(1)?))
+        .cloned()
+        .with_context(|| format!("no worksheet {sheet:?}; the workbook has {names:?}"))
+}
+
+/// 和读 CSV 一样写成文本：数字写成 `1`、`9.5`，日期写成 `2024-01-31`，带时间的写成
+/// `2024-01-31T08:00:00`。
+fn text(cell: &Data) -> String {
+    match cell {
+        Data::DateTime(dt) if dt.is_datetime() => match dt.as_datetime() {
+            Some(dt) if dt.date().and_hms_opt(0, 0, 0) == Some(dt) => dt.date().to_string(),
+            Some(dt) => dt.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+            None => cell.to_string(),
+        },
+        // 浮点的 Display 本来就把 1.0 写成 `1`；空单元格写成空串
+        cell => cell.to_string(),
+    }
+}
+
+/// 逐条收下记录写进一个工作表，[`finish`](Self::finish) 时编成 `.xlsx` 的字节。
+///
+/// 第一行是加粗并冻结的表头；数字、布尔写成 Excel 的数字和布尔，日期列写成带日期格式的日期，
+/// 其余写成文本。整个工作簿在内存里攒好，最后一次写出。
+pub(crate) struct XlsxWriter {
+    sheet: Worksheet,
+    /// 表头；没给就取第一条记录的键
+    columns: Option<Vec<String>>,
+    dates: Vec<String>,
+    /// 下一条记录写在第几行，0 表示表头还没写
+    row: u32,
+    date: Format,
+    datetime: Format,
+}
+
+impl std::fmt::Debug for XlsxWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("XlsxWriter")
+            .field("columns", &self.columns)
+            .field("row", &self.row)
+            .finish_non_exhaustive()
+    }
+}
+
+impl XlsxWriter {
+    /// `dates` 是日期列的列名，这些列里能解析成日期的值写成 Excel 日期。
+    pub(crate) fn new(columns: Option<Vec<String>>, dates: Vec<String>) -> Self {
+        Self {
+            sheet: Worksheet::new(),
+            columns,
+            dates,
+            row: 0,
+            date: Format::new().set_num_format("yyyy-mm-dd"),
+            datetime: Format::new().set_num_format("yyyy-mm-dd hh:mm:ss"),
+        }
+    }
+
+    pub(crate) fn push(&mut self, record: &Value) -> Result<()> {
+        if self.row == 0 {
+            let columns = self.columns.get_or_insert_with(|| keys(record)).clone();
+            self.header(&columns)?;
+        }
+        if self.row >= MAX_ROWS {
+            bail!("xlsx holds at most {} records per sheet", MAX_ROWS - 1);
+        }
+        let columns = self.columns.as_deref().unwrap_or_default();
+        for (col, name) in columns.iter().enumerate() {
+            let col = u16::try_from(col).context("xlsx holds at most 16384 columns")?;
+            let row = self.row;
+            match record.get(name).unwrap_or(&Value::Null) {
+                Value::Null => {}
+                Value::Bool(b) => {
+                    self.sheet.write_boolean(row, col, *b)?;
+                }
+                Value::Number(n) => {
+                    self.sheet
+                        .write_number(row, col, n.as_f64().unwrap_or_default())?;
+                }
+                Value::String(s) => match self.date_serial(name, s) {
+                    Some((serial, format)) => {
+                        let format = if format { &self.datetime } else { &self.date };
+                        self.sheet
+                            .write_number_with_format(row, col, serial, format)?;
+                    }
+                    None => {
+                        self.sheet.write_string(row, col, s)?;
+                    }
+                },
+                value => {
+                    self.sheet.write_string(row, col, cell(value))?;
+                }
+            }
+        }
+        self.row += 1;
+        Ok(())
+    }
+
+    /// 编成 `.xlsx`；一条记录都没有时也写出表头。
+    pub(crate) fn finish(mut self) -> Result<Vec<u8>> {
+        if self.row == 0 {
+            let columns = self.columns.clone().unwrap_or_default();
+            self.header(&columns)?;
+        }
+        self.sheet.autofit();
+        let mut workbook = Workbook::new();
+        workbook.push_worksheet(self.sheet);
+        Ok(workbook.save_to_buffer()?)
+    }
+
+    fn header(&mut self, columns: &[String]) -> Result<()> {
+        let bold = Format::new().set_bold();
+        for (col, name) in columns.iter().enumerate() {
+            let col = u16::try_from(col).context("xlsx holds at most 16384 columns")?;
+            self.sheet.write_string_with_format(0, col, name, &bold)?;
+        }
+        self.sheet.set_freeze_panes(1, 0)?;
+        self.row = 1;
+        Ok(())
+    }
+
+    /// 日期列里的值转成 Excel 序列号，第二个值表示带不带时间；只有时间的不算。
+    fn date_serial(&self, name: &str, raw: &str) -> Option<(f64, bool)> {
+        if !self.dates.iter().any(|d| d == name) {
+            return None;
+        }
+        let dt = parse_date(raw)?;
+        let date = dt.date?;
+        if dt.time.is_none() {
+            return Some((f64::from(days(date) + UNIX_EPOCH_SERIAL), false));
+        }
+        #[expect(clippy::cast_precision_loss, reason = "2255 年之前的微秒数都小于 2^53")]
+        let days = micros(dt) as f64 / 86_400_000_000.0;
+        Some((days + f64::from(UNIX_EPOCH_SERIAL), true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_xlsx_roundtrip() -> Result<()> {
+        let mut writer = XlsxWriter::new(None, vec!["born".to_owned()]);
+        writer.push(&json!({ "name": "Buffon", "kit": 1, "rating": 9.5, "born": "1978-01-28" }))?;
+        writer.push(
+            &json!({ "name": "Perin", "kit": null, "rating": 7, "born": "1992-11-10T08:30:00Z" }),
+        )?;
+        let bytes = writer.finish()?;
+        assert!(is_workbook(&bytes));
+
+        let (name, rows) = read_sheet(bytes, Some("1"))?;
+        assert_eq!(name, "Sheet1");
+        let rows: Vec<Vec<&str>> = rows.iter().map(|row| row.iter().collect()).collect();
+        assert_eq!(
+            rows,
+            [
+                ["name", "kit", "rating", "born"],
+                ["Buffon", "1", "9.5", "1978-01-28"],
+                ["Perin", "", "7", "1992-11-10T08:30:00"],
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_pick_sheet_by_name_or_index() -> Result<()> {
+        let names = ["Players".to_owned(), "2".to_owned(), "Staff".to_owned()];
+        assert_eq!(pick(&names, None)?, "Players");
+        assert_eq!(pick(&names, Some("Staff"))?, "Staff");
+        // 名字优先于序号
+        assert_eq!(pick(&names, Some("2"))?, "2");
+        assert_eq!(pick(&names, Some("3"))?, "Staff");
+        assert!(pick(&names, Some("0")).is_err());
+        assert!(pick(&names, Some("Coaches")).is_err());
+        Ok(())
+    }
+}
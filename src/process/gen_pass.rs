@@ -1,61 +1,308 @@
-use anyhow::Context;
+use anyhow::{Context, bail};
 use rand::seq::{IndexedRandom, SliceRandom};
 
-const UPPER: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
-const LOWER: &[u8] = b"abcdefghijkmnopqrstuvwxyz";
-const NUMBER: &[u8] = b"123456789";
-const SYMBOL: &[u8] = b"!@#$%^&*_";
+use crate::cli::CharClass;
 
-/// # Errors
-#[expect(clippy::fn_params_excessive_bools)]
-pub fn process_genpass(
-    length: u8,
-    no_upper: bool,
-    no_lower: bool,
-    no_number: bool,
-    no_symbol: bool,
-) -> anyhow::Result<String> {
-    let mut rng = rand::rng();
-    let mut password = Vec::new();
-    let mut chars = Vec::new();
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+pub(crate) const DIGIT: &str = "0123456789";
+/// 默认的符号，大多数系统都收
+pub(crate) const SYMBOL: &str = "!@#$%^&*_";
+/// 容易看错的字符，默认不用
+const AMBIGUOUS: &str = "0OIl";
+/// 有 `start_with` / `max_run` 时最多重抽几次
+const MAX_ATTEMPTS: usize = 1000;
+
+/// [`process_genpass`] 的选项。
+///
+/// 字符池是 `charset`，没给时是开着的几类字符拼起来，再（默认）去掉易混字符；最后去掉 `exclude`。
+/// `charset` 是调用方明确给的字母表，照原样用，不去易混字符。
+/// 池里的字符按大写、小写、数字、其他（符号）分类，每类至少出 `min_*` 个；`min_*` 没给时，
+/// 池里有这类字符就至少出 1 个。
+///
+/// `start_with` 和 `max_run` 靠整体重抽满足，抽到的结果在满足条件的密码里仍然是均匀的。
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = "四类字符各自独立开关，和命令行的 --no-* 一一对应"
+)]
+#[derive(Debug, Clone)]
+pub struct GenPassOptions {
+    pub length: usize,
+    pub upper: bool,
+    pub lower: bool,
+    pub digit: bool,
+    pub symbol: bool,
+    /// 换掉默认的符号集 `!@#$%^&*_`
+    pub symbols: Option<String>,
+    /// 直接指定整个字符池，忽略上面四个开关和 `symbols`
+    pub charset: Option<String>,
+    /// 从字符池里去掉这些字符
+    pub exclude: String,
+    /// 内置的几类字符里保留 `0 O I l` 这几个易混字符；对 `charset` 不起作用
+    pub allow_ambiguous: bool,
+    pub min_upper: Option<usize>,
+    pub min_lower: Option<usize>,
+    pub min_digit: Option<usize>,
+    pub min_symbol: Option<usize>,
+    /// 第一个字符必须属于这一类
+    pub start_with: Option<CharClass>,
+    /// 同一个字符最多连着出现几次
+    pub max_run: Option<usize>,
+}
 
-    if !no_upper {
-        chars.extend_from_slice(UPPER);
-        password.push(*UPPER.choose(&mut rng).context("UPPER won't be empty")?);
+impl Default for GenPassOptions {
+    fn default() -> Self {
+        Self {
+            length: 16,
+            upper: true,
+            lower: true,
+            digit: true,
+            symbol: true,
+            symbols: None,
+            charset: None,
+            exclude: String::new(),
+            allow_ambiguous: false,
+            min_upper: None,
+            min_lower: None,
+            min_digit: None,
+            min_symbol: None,
+            start_with: None,
+            max_run: None,
+        }
     }
+}
 
-    if !no_lower {
-        chars.extend_from_slice(LOWER);
-        password.push(*LOWER.choose(&mut rng).context("LOWER won't be empty")?);
+impl GenPassOptions {
+    /// 熵的估计值（bit）：按从整个字符池均匀挑选算，`length × log2(池大小)`。
+    /// 配额和 `start_with` / `max_run` 会让实际值略低一点。
+    ///
+    /// # Errors
+    ///
+    /// 字符池为空或配额要求的字符类不在池里时返回错误。
+    pub fn entropy(&self) -> anyhow::Result<f64> {
+        let pool = Pool::new(self)?;
+        #[expect(clippy::cast_precision_loss, reason = "长度和字符池都远小于 2^52")]
+        let bits = self.length as f64 * (pool.all.len() as f64).log2();
+        Ok(bits)
     }
-    if !no_number {
-        chars.extend_from_slice(NUMBER);
-        password.push(*NUMBER.choose(&mut rng).context("NUMBER won't be empty")?);
+}
+
+/// 字符池按类分开，配额按类挑。
+#[derive(Debug)]
+struct Pool {
+    all: Vec<char>,
+    /// 大写、小写、数字、符号各自的字符和至少要几个
+    classes: [(&'static str, Vec<char>, usize); 4],
+}
+
+impl Pool {
+    fn new(opts: &GenPassOptions) -> anyhow::Result<Self> {
+        let mut all: Vec<char> = if let Some(charset) = &opts.charset {
+            charset.chars().collect()
+        } else {
+            let keep = |c: &char| opts.allow_ambiguous || !AMBIGUOUS.contains(*c);
+            let symbols = opts.symbols.as_deref().unwrap_or(SYMBOL);
+            [
+                (opts.upper, UPPER),
+                (opts.lower, LOWER),
+                (opts.digit, DIGIT),
+                (opts.symbol, symbols),
+            ]
+            .into_iter()
+            .filter(|(on, _)| *on)
+            .flat_map(|(_, set)| set.chars())
+            .filter(keep)
+            .collect()
+        };
+        all.retain(|c| !opts.exclude.contains(*c));
+        all.sort_unstable();
+        all.dedup();
+        if all.is_empty() {
+            bail!("no characters left to choose from after --exclude and ambiguous characters");
+        }
+
+        let class = |name, min: Option<usize>, pick: fn(char) -> bool| {
+            let chars: Vec<char> = all.iter().copied().filter(|c| pick(*c)).collect();
+            let min = min.unwrap_or(usize::from(!chars.is_empty()));
+            if min > 0 && chars.is_empty() {
+                bail!(
+                    "--min-{name} {min} asks for {name} characters, but none are left to choose \
+                     from"
+                );
+            }
+            Ok((name, chars, min))
+        };
+        let classes = [
+            class("upper", opts.min_upper, char::is_uppercase)?,
+            class("lower", opts.min_lower, char::is_lowercase)?,
+            class("digit", opts.min_digit, char::is_numeric)?,
+            class("symbol", opts.min_symbol, |c| !c.is_alphanumeric())?,
+        ];
+        Ok(Self { all, classes })
     }
-    if !no_symbol {
-        chars.extend_from_slice(SYMBOL);
-        password.push(*SYMBOL.choose(&mut rng).context("SYMBOL won't be empty")?);
+
+    /// 各类配额加起来最少要多长。
+    fn required(&self) -> usize {
+        self.classes.iter().map(|(_, _, min)| min).sum()
     }
 
-    if chars.is_empty() {
-        anyhow::bail!("At least one character set must be enabled");
+    /// 提前排除一定生成不出来的组合，免得白白重抽。
+    fn check(&self, opts: &GenPassOptions) -> anyhow::Result<()> {
+        let length = opts.length;
+        if length == 0 {
+            bail!("length must be at least 1");
+        }
+        let required = self.required();
+        let quotas: Vec<String> = self
+            .classes
+            .iter()
+            .filter(|(_, _, min)| *min > 0)
+            .map(|(name, _, min)| format!("{min} {name}"))
+            .collect();
+        if length < required {
+            bail!(
+                "length {length} is too short: at least {required} characters are required ({})",
+                quotas.join(", ")
+            );
+        }
+        if let Some(start) = opts.start_with {
+            if !self.all.iter().any(|c| start.contains(*c)) {
+                bail!("the password must start with {start}, but none are left to choose from");
+            }
+            let startable = self
+                .classes
+                .iter()
+                .any(|(_, chars, min)| *min > 0 && chars.iter().any(|c| start.contains(*c)));
+            if length == required && !startable {
+                bail!(
+                    "the password must start with {start}, but all {length} characters go to the \
+                     quotas ({}), none of which is {start}",
+                    quotas.join(", ")
+                );
+            }
+        }
+        if let Some(max_run) = opts.max_run {
+            if max_run == 0 {
+                bail!("max run must be at least 1");
+            }
+            if let [only] = self.all[..]
+                && length > max_run
+            {
+                bail!(
+                    "{only:?} is the only character to choose from, so a password of length \
+                     {length} repeats it more than {max_run} times in a row"
+                );
+            }
+            // 只有一个字符的类，配额得靠别的字符隔开
+            for (name, chars, min) in &self.classes {
+                if chars.len() == 1 && *min > 0 && min.div_ceil(max_run) - 1 > length - min {
+                    bail!(
+                        "{min} {name} characters can only be {:?}, and {length} characters are \
+                         too few to keep it from repeating more than {max_run} times in a row",
+                        chars[0]
+                    );
+                }
+            }
+        }
+        Ok(())
     }
 
-    let seeded = password.len();
-    let length = length as usize;
-    if length < seeded {
-        anyhow::bail!("length {length} is too short: {seeded} character set(s) enabled");
+    /// 先按配额挑，剩下的从整个池子里挑，最后打乱。
+    fn generate(&self, length: usize) -> anyhow::Result<Vec<char>> {
+        let required = self.required();
+        let mut rng = rand::rng();
+        let mut password = Vec::with_capacity(length);
+        for (_, chars, min) in &self.classes {
+            for _ in 0..*min {
+                password.push(*chars.choose(&mut rng).context("class won't be empty")?);
+            }
+        }
+        for _ in required..length {
+            password.push(*self.all.choose(&mut rng).context("pool won't be empty")?);
+        }
+        password.shuffle(&mut rng);
+        Ok(password)
     }
-    for _ in 0..(length - seeded) {
-        let c = chars
-            .choose(&mut rng)
-            .context("chars won't be empty in this context")?;
-        password.push(*c);
+}
+
+/// # Errors
+///
+/// 字符池、配额、长度和 `start_with` / `max_run` 凑不到一起时返回错误，说明是哪条规则不满足。
+pub fn process_genpass(opts: &GenPassOptions) -> anyhow::Result<String> {
+    let pool = Pool::new(opts)?;
+    pool.check(opts)?;
+    for _ in 0..MAX_ATTEMPTS {
+        let password = pool.generate(opts.length)?;
+        let starts_ok = opts
+            .start_with
+            .is_none_or(|start| start.contains(password[0]));
+        let runs_ok = opts
+            .max_run
+            .is_none_or(|max_run| longest_run(&password) <= max_run);
+        if starts_ok && runs_ok {
+            return Ok(password.into_iter().collect());
+        }
     }
+    bail!(
+        "gave up after {MAX_ATTEMPTS} attempts: the start and repeated-run rules are too strict \
+         for this character pool and length"
+    )
+}
 
-    password.shuffle(&mut rng);
+/// 同一个字符最长连着出现几次。
+fn longest_run(password: &[char]) -> usize {
+    password
+        .chunk_by(|a, b| a == b)
+        .map(<[char]>::len)
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let password = String::from_utf8(password)?;
+    #[test]
+    fn test_genpass_quotas_and_exclusions() -> anyhow::Result<()> {
+        let opts = GenPassOptions {
+            length: 12,
+            symbols: Some("-+".to_owned()),
+            exclude: "abc".to_owned(),
+            min_digit: Some(5),
+            min_symbol: Some(0),
+            ..GenPassOptions::default()
+        };
+        for _ in 0..50 {
+            let password = process_genpass(&opts)?;
+            assert_eq!(password.chars().count(), 12);
+            assert!(password.chars().filter(char::is_ascii_digit).count() >= 5);
+            assert!(password.chars().any(|c| c.is_ascii_uppercase()));
+            assert!(!password.contains(['a', 'b', 'c', '0', 'O', 'I', 'l', '!']));
+        }
 
-    Ok(password)
+        // 自定义字符池照原样用，易混字符也在里面
+        let opts = GenPassOptions {
+            length: 8,
+            charset: Some("0123456789".to_owned()),
+            min_digit: Some(8),
+            ..GenPassOptions::default()
+        };
+        assert!((opts.entropy()? - 8.0 * 10f64.log2()).abs() < 1e-9);
+        assert!((0..200).any(|_| process_genpass(&opts).is_ok_and(|p| p.contains('0'))));
+
+        // 配额超过长度、要求的类被排除光了，都报错
+        let too_many = GenPassOptions {
+            length: 8,
+            min_upper: Some(9),
+            ..GenPassOptions::default()
+        };
+        assert!(process_genpass(&too_many).is_err());
+        let no_digits = GenPassOptions {
+            exclude: DIGIT.to_owned(),
+            min_digit: Some(1),
+            ..GenPassOptions::default()
+        };
+        assert!(process_genpass(&no_digits).is_err());
+        Ok(())
+    }
 }
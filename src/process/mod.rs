@@ -1,17 +1,50 @@
 mod b64;
+mod csv_columnar;
+mod csv_columns;
 mod csv_convert;
+mod csv_dialect;
+mod csv_diff;
+mod csv_filter;
+mod csv_from;
+mod csv_infer;
+mod csv_join;
+mod csv_nested;
+mod csv_show;
+mod csv_sort;
+mod csv_split;
+mod csv_stats;
+mod csv_typed;
+mod csv_validate;
+mod csv_xlsx;
 mod gen_pass;
 mod http_index;
 mod http_serve;
 mod jwt;
+mod passphrase;
+mod password_policy;
+mod password_strength;
 mod text;
 
 pub use b64::{process_decode, process_encode};
-pub use csv_convert::process_csv;
-pub use gen_pass::process_genpass;
+pub use csv_convert::{CsvOptions, CsvOutput, convert_csv, convert_csv_to_string, process_csv};
+pub use csv_dialect::CsvReadOptions;
+pub use csv_diff::{Change, CsvDiff, CsvDiffOptions, FieldChange, process_csv_diff};
+pub use csv_from::process_csv_from;
+pub use csv_join::{CsvJoinOptions, process_csv_join};
+pub use csv_show::{CsvShowRange, process_csv_show};
+pub use csv_split::{CsvSplitOptions, SplitBy, SplitPart, process_csv_split};
+pub use csv_stats::{CsvStatsOptions, process_csv_stats};
+pub use csv_typed::{CsvError, CsvReader, records_to_string, write_records};
+pub use csv_validate::{CsvValidationReport, Violation, process_csv_validate};
+pub use gen_pass::{GenPassOptions, process_genpass};
 pub use http_index::process_http_index;
 pub use http_serve::process_http_serve;
 pub use jwt::{Claims, process_jwt_pubkey, process_jwt_sign, process_jwt_verify};
+pub use passphrase::{Passphrase, PassphraseOptions, Wordlist, process_passphrase};
+pub use password_policy::PasswordPolicy;
+pub use password_strength::{
+    GeneratedPassword, StrengthReport, process_password_check, write_generated,
+};
 pub use text::{
     process_text_decrypt, process_text_encrypt, process_text_generate, process_text_sign,
     process_text_verify,
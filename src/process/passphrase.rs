@@ -0,0 +1,187 @@
+//! `genpass --words`：从词表里随机挑词拼成好记的口令（diceware）。
+
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use rand::{RngExt, seq::IndexedRandom};
+
+use crate::{
+    cli::{Capitalize, PassphraseSuffix},
+    process::gen_pass::{DIGIT, SYMBOL},
+};
+
+/// 去过重的词表。按 EFF 的格式读：每行
+/// `11111<Tab>abacus`，前面的骰子编号可有可无，也可以一行一个词。
+#[derive(Debug, Clone)]
+pub struct Wordlist {
+    words: Vec<String>,
+}
+
+impl Wordlist {
+    /// # Errors
+    ///
+    /// 不到两个词时返回错误。
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut words: Vec<String> = Vec::new();
+        for line in text.lines() {
+            let mut fields = line.split_whitespace();
+            let word = match (fields.next(), fields.next(), fields.next()) {
+                (None, ..) => continue,
+                (Some(word), None, _) => word,
+                (Some(dice), Some(word), None) if dice.bytes().all(|b| b.is_ascii_digit()) => word,
+                _ => bail!("wordlist line {line:?} is neither `word` nor `dice<Tab>word`"),
+            };
+            words.push(word.to_owned());
+        }
+        words.sort_unstable();
+        words.dedup();
+        if words.len() < 2 {
+            bail!(
+                "wordlist needs at least 2 distinct words, got {}",
+                words.len()
+            );
+        }
+        Ok(Self { words })
+    }
+
+    /// # Errors
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read wordlist {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("invalid wordlist {}", path.display()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+/// [`process_passphrase`] 的选项。
+#[derive(Debug, Clone)]
+pub struct PassphraseOptions {
+    pub words: usize,
+    pub separator: String,
+    pub capitalize: Capitalize,
+    /// 末尾再补一个数字或符号，应付要求「必须含数字 / 符号」的系统
+    pub suffix: Option<PassphraseSuffix>,
+}
+
+impl Default for PassphraseOptions {
+    fn default() -> Self {
+        Self {
+            words: 6,
+            separator: "-".to_owned(),
+            capitalize: Capitalize::Lower,
+            suffix: None,
+        }
+    }
+}
+
+/// 生成的口令和它的熵。
+#[derive(Debug, Clone)]
+pub struct Passphrase {
+    pub phrase: String,
+    /// 单位是 bit
+    pub entropy: f64,
+}
+
+/// 熵按均匀随机挑选计算：每个词 `log2(词数)` 位，随机大小写每个词再加 1 位，末尾补的数字或符号加上
+/// `log2(候选个数)` 位。
+///
+/// # Errors
+///
+/// 词数为 0 时返回错误。
+pub fn process_passphrase(wordlist: &Wordlist, opts: &PassphraseOptions) -> Result<Passphrase> {
+    if opts.words == 0 {
+        bail!("a passphrase needs at least 1 word");
+    }
+    let mut rng = rand::rng();
+    let mut tokens = Vec::with_capacity(opts.words + 1);
+    for _ in 0..opts.words {
+        let word = wordlist
+            .words
+            .choose(&mut rng)
+            .context("wordlist won't be empty")?;
+        tokens.push(match opts.capitalize {
+            Capitalize::Title => title_case(word),
+            Capitalize::Upper => word.to_uppercase(),
+            Capitalize::Random if rng.random() => title_case(word),
+            Capitalize::Lower | Capitalize::Random => word.clone(),
+        });
+    }
+
+    #[expect(clippy::cast_precision_loss, reason = "词表和词数都远小于 2^52")]
+    let mut entropy = opts.words as f64 * (wordlist.len() as f64).log2();
+    if matches!(opts.capitalize, Capitalize::Random) {
+        #[expect(clippy::cast_precision_loss, reason = "同上")]
+        let bits = opts.words as f64;
+        entropy += bits;
+    }
+    if let Some(suffix) = opts.suffix {
+        let set: Vec<char> = match suffix {
+            PassphraseSuffix::Digit => DIGIT,
+            PassphraseSuffix::Symbol => SYMBOL,
+        }
+        .chars()
+        .collect();
+        tokens.push(
+            set.choose(&mut rng)
+                .context("set won't be empty")?
+                .to_string(),
+        );
+        #[expect(clippy::cast_precision_loss, reason = "同上")]
+        let bits = (set.len() as f64).log2();
+        entropy += bits;
+    }
+
+    Ok(Passphrase {
+        phrase: tokens.join(&opts.separator),
+        entropy,
+    })
+}
+
+fn title_case(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passphrase_shape_and_entropy() -> Result<()> {
+        let wordlist = Wordlist::parse("11111\tabacus\n11112\tabdomen\n\n11113\tabacus\nzebra\n")?;
+        assert_eq!(wordlist.len(), 3);
+
+        let opts = PassphraseOptions {
+            words: 4,
+            separator: ".".to_owned(),
+            capitalize: Capitalize::Title,
+            suffix: Some(PassphraseSuffix::Digit),
+        };
+        let pass = process_passphrase(&wordlist, &opts)?;
+        let tokens: Vec<&str> = pass.phrase.split('.').collect();
+        assert_eq!(tokens.len(), 5);
+        assert!(
+            tokens[..4]
+                .iter()
+                .all(|t| ["Abacus", "Abdomen", "Zebra"].contains(t))
+        );
+        assert!(tokens[4].parse::<u8>().is_ok());
+        let expected = 4.0 * 3f64.log2() + 10f64.log2();
+        assert!((pass.entropy - expected).abs() < 1e-9);
+
+        assert!(Wordlist::parse("only\n").is_err());
+        assert!(Wordlist::parse("a b c\n").is_err());
+        Ok(())
+    }
+}
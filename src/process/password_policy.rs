@@ -0,0 +1,228 @@
+//! `genpass --policy`：按目标系统的密码规则生成。
+
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::{cli::CharClass, process::gen_pass::GenPassOptions};
+
+/// 内置配置：名字和 YAML 写的规则，本身也是写规则文件的例子。
+const BUILTIN: [(&str, &str); 4] = [
+    (
+        "aws-iam",
+        r#"
+# AWS IAM 用户：8 到 128 位，符号只认这几个
+min_length: 8
+max_length: 128
+length: 20
+symbols: "!@#$%^&*()_+-=[]{}|'"
+"#,
+    ),
+    (
+        "active-directory",
+        r#"
+# Active Directory：复杂度要求四类里至少占三类，这里四类都要；符号用 ASCII 的全部标点
+min_length: 8
+max_length: 256
+length: 16
+symbols: '~!@#$%^&*_-+=`|\(){}[]:;"''<>,.?/'
+"#,
+    ),
+    (
+        "oracle",
+        r#"
+# Oracle 数据库：最长 30 位，必须字母开头，不加引号时符号只能用 _ $ #
+min_length: 8
+max_length: 30
+length: 16
+symbols: "_$#"
+start_with: letter
+"#,
+    ),
+    (
+        "mainframe",
+        r#"
+# 老式大型机（RACF）：正好 8 位，只有大写字母、数字和 @ # $，字母开头，同一字符最多连续 2 个
+min_length: 8
+max_length: 8
+charset: "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789@#$"
+start_with: letter
+max_run: 2
+"#,
+    ),
+];
+
+/// 不指定长度时用的长度，会夹到策略允许的范围里。
+const DEFAULT_LENGTH: usize = 16;
+
+/// 一份密码策略：字段和 [`GenPassOptions`] 一一对应，另外多了允许的长度范围。没写的字段取
+/// [`GenPassOptions::default`] 的值。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PasswordPolicy {
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    /// 默认长度，没写时取 16 并夹到 `min_length..=max_length` 里
+    pub length: Option<usize>,
+    pub upper: Option<bool>,
+    pub lower: Option<bool>,
+    pub digit: Option<bool>,
+    pub symbol: Option<bool>,
+    pub symbols: Option<String>,
+    pub charset: Option<String>,
+    #[serde(default)]
+    pub exclude: String,
+    #[serde(default)]
+    pub allow_ambiguous: bool,
+    pub min_upper: Option<usize>,
+    pub min_lower: Option<usize>,
+    pub min_digit: Option<usize>,
+    pub min_symbol: Option<usize>,
+    pub start_with: Option<CharClass>,
+    pub max_run: Option<usize>,
+}
+
+impl PasswordPolicy {
+    /// 内置配置的名字。
+    pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+        BUILTIN.iter().map(|(name, _)| *name)
+    }
+
+    /// 按名字取内置配置。
+    ///
+    /// # Panics
+    ///
+    /// 只在内置配置本身写错时 panic，测试覆盖了每一份。
+    pub fn builtin(name: &str) -> Option<Self> {
+        let (_, yaml) = BUILTIN.iter().find(|(builtin, _)| *builtin == name)?;
+        Some(serde_saphyr::from_str(yaml).expect("built-in policies are valid YAML"))
+    }
+
+    /// 先按内置配置的名字找，找不到再当文件路径读；格式看扩展名，YAML / TOML / JSON，没有扩展名按
+    /// YAML。
+    ///
+    /// # Errors
+    ///
+    /// 既不是内置名字也不是可读的策略文件时返回错误。
+    pub fn load(spec: &str) -> Result<Self> {
+        if let Some(policy) = Self::builtin(spec) {
+            return Ok(policy);
+        }
+        let path = Path::new(spec);
+        if !path.exists() {
+            bail!(
+                "unknown policy {spec:?}: not a file, nor one of the built-in profiles ({})",
+                Self::builtin_names().collect::<Vec<_>>().join(", ")
+            );
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read policy {spec}"))?;
+        let policy = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content)?,
+            Some("toml") => toml::from_str(&content)?,
+            Some("yaml" | "yml") | None => serde_saphyr::from_str(&content)?,
+            Some(ext) => bail!("policy must be JSON, YAML or TOML, got .{ext}"),
+        };
+        Ok(policy)
+    }
+
+    /// 转成 [`process_genpass`](crate::process_genpass) 的选项；`length` 覆盖策略里的默认长度。
+    ///
+    /// # Errors
+    ///
+    /// 长度范围本身不成立，或者 `length` 落在范围外时返回错误。
+    pub fn options(&self, length: Option<usize>) -> Result<GenPassOptions> {
+        let min = self.min_length.unwrap_or(1);
+        let max = self.max_length.unwrap_or(usize::MAX);
+        if min > max {
+            bail!("policy min_length {min} is greater than max_length {max}");
+        }
+        let length = length
+            .or(self.length)
+            .unwrap_or_else(|| DEFAULT_LENGTH.clamp(min, max));
+        if !(min..=max).contains(&length) {
+            bail!(
+                "length {length} is outside what the policy allows ({})",
+                if max == usize::MAX {
+                    format!("at least {min}")
+                } else {
+                    format!("{min} to {max}")
+                }
+            );
+        }
+        let defaults = GenPassOptions::default();
+        Ok(GenPassOptions {
+            length,
+            upper: self.upper.unwrap_or(defaults.upper),
+            lower: self.lower.unwrap_or(defaults.lower),
+            digit: self.digit.unwrap_or(defaults.digit),
+            symbol: self.symbol.unwrap_or(defaults.symbol),
+            symbols: self.symbols.clone(),
+            charset: self.charset.clone(),
+            exclude: self.exclude.clone(),
+            allow_ambiguous: self.allow_ambiguous,
+            min_upper: self.min_upper,
+            min_lower: self.min_lower,
+            min_digit: self.min_digit,
+            min_symbol: self.min_symbol,
+            start_with: self.start_with,
+            max_run: self.max_run,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_genpass;
+
+    #[test]
+    fn test_builtin_policies_are_satisfiable() -> Result<()> {
+        for name in PasswordPolicy::builtin_names() {
+            let policy = PasswordPolicy::load(name)?;
+            let opts = policy.options(None)?;
+            for _ in 0..20 {
+                let password = process_genpass(&opts)?;
+                assert!(opts.start_with.is_none_or(|start| {
+                    password.chars().next().is_some_and(|c| start.contains(c))
+                }));
+            }
+        }
+
+        let mainframe = PasswordPolicy::load("mainframe")?.options(None)?;
+        for _ in 0..50 {
+            let password: Vec<char> = process_genpass(&mainframe)?.chars().collect();
+            assert_eq!(password.len(), 8);
+            assert!(password[0].is_ascii_uppercase());
+            assert!(password.windows(3).all(|w| !(w[0] == w[1] && w[1] == w[2])));
+            assert!(password.iter().all(|c| !c.is_lowercase()));
+        }
+        assert!(PasswordPolicy::load("oracle")?.options(Some(31)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsatisfiable_policy_explains_why() -> Result<()> {
+        let policy: PasswordPolicy = serde_saphyr::from_str(
+            "min_length: 4\nmax_length: 4\ncharset: '0123456789'\nallow_ambiguous: \
+             true\nstart_with: letter\n",
+        )?;
+        let err = process_genpass(&policy.options(None)?).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the password must start with a letter, but none are left to choose from"
+        );
+
+        let policy: PasswordPolicy =
+            toml::from_str("length = 6\ncharset = \"ab\"\nmin_lower = 6\nmax_run = 1\n")?;
+        // a、b 交替就行
+        let password = process_genpass(&policy.options(None)?)?;
+        assert!(password == "ababab" || password == "bababa");
+
+        let policy: PasswordPolicy = toml::from_str("length = 3\ncharset = \"a\"\nmax_run = 2\n")?;
+        assert!(process_genpass(&policy.options(None)?).is_err());
+        assert!(serde_saphyr::from_str::<PasswordPolicy>("min_len: 8\n").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+//! 用 zxcvbn 给密码打分：`genpass --count` 的每一条输出是一个 [`GeneratedPassword`]，
+//! `genpass check` 的每一条是一个 [`StrengthReport`]。
+
+use std::fmt;
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use zxcvbn::{Entropy, feedback::Feedback, zxcvbn};
+
+use crate::{cli::OutputFormat, get_reader, process::csv_convert::RecordWriter};
+
+/// 一条生成结果和它的强度评估。
+///
+/// 字段顺序就是 CSV 的列顺序。`crack_time` 按离线攻击慢哈希（每秒 1 万次）估算。
+#[derive(Debug, Clone, Serialize)]
+pub struct GeneratedPassword {
+    pub password: String,
+    /// zxcvbn 评分，0（最弱）到 4
+    pub score: u8,
+    /// 生成方式决定的熵，单位 bit，保留一位小数
+    pub entropy: f64,
+    pub crack_time: String,
+}
+
+impl GeneratedPassword {
+    /// `entropy` 由生成方给出（[`GenPassOptions::entropy`](crate::GenPassOptions::entropy)、
+    /// [`Passphrase::entropy`](crate::Passphrase)），zxcvbn 只管评分和破解时间。
+    pub fn new(password: String, entropy: f64) -> Self {
+        let result = zxcvbn(&password, &[]);
+        Self {
+            score: result.score().into(),
+            entropy: (entropy * 10.0).round() / 10.0,
+            crack_time: result
+                .crack_times()
+                .offline_slow_hashing_1e4_per_second()
+                .to_string(),
+            password,
+        }
+    }
+}
+
+/// 生成 `count` 个密码写进 `writer`，生成一个写一个，不在内存里攒。
+///
+/// `format` 为 `None` 时一行一个密码，不跑 zxcvbn；给了格式才评分，每个密码一条
+/// [`GeneratedPassword`] 记录。`generate` 每次返回一个密码和它的熵。
+///
+/// # Errors
+///
+/// 生成失败、格式是二进制或者写入失败时返回错误。
+pub async fn write_generated<W, F>(
+    mut writer: W,
+    count: u64,
+    format: Option<OutputFormat>,
+    mut generate: F,
+) -> Result<W>
+where
+    W: AsyncWrite + Unpin,
+    F: FnMut() -> Result<(String, f64)>,
+{
+    let Some(format) = format else {
+        for _ in 0..count {
+            let (password, _) = generate()?;
+            writer.write_all(password.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+        }
+        writer.flush().await?;
+        return Ok(writer);
+    };
+    if format.is_binary() {
+        bail!("{format} output is binary, pick json, ndjson, yaml, toml or csv");
+    }
+    let mut records = RecordWriter::new(writer, format);
+    for _ in 0..count {
+        let (password, entropy) = generate()?;
+        let entry = GeneratedPassword::new(password, entropy);
+        records.write(&serde_json::to_value(&entry)?).await?;
+    }
+    records.finish().await
+}
+
+/// 一个候选密码的评估结果。不带密码本身，按输入里的行号对应，免得审计输出把密码又抄一遍。
+///
+/// 四个破解时间对应 zxcvbn 的四种攻击场景：有限速的在线攻击（每小时 100 次）、不限速的在线攻击
+/// （每秒 10 次）、离线慢哈希（每秒 1 万次）、离线快哈希（每秒 100 亿次）。
+#[derive(Debug, Clone, Serialize)]
+pub struct StrengthReport {
+    /// 从 1 开始的行号
+    pub line: usize,
+    /// zxcvbn 评分，0（最弱）到 4
+    pub score: u8,
+    pub guesses: u64,
+    pub guesses_log10: f64,
+    pub online_throttled: String,
+    pub online_unthrottled: String,
+    pub offline_slow_hash: String,
+    pub offline_fast_hash: String,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+impl StrengthReport {
+    fn new(line: usize, result: &Entropy) -> Self {
+        let times = result.crack_times();
+        let feedback = result.feedback();
+        Self {
+            line,
+            score: result.score().into(),
+            guesses: result.guesses(),
+            guesses_log10: (result.guesses_log10() * 100.0).round() / 100.0,
+            online_throttled: times.online_throttling_100_per_hour().to_string(),
+            online_unthrottled: times.online_no_throttling_10_per_second().to_string(),
+            offline_slow_hash: times.offline_slow_hashing_1e4_per_second().to_string(),
+            offline_fast_hash: times.offline_fast_hashing_1e10_per_second().to_string(),
+            warning: feedback
+                .and_then(Feedback::warning)
+                .map(|warning| warning.to_string()),
+            suggestions: feedback
+                .map(|feedback| {
+                    feedback
+                        .suggestions()
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for StrengthReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "line {}: score {}/4, about 10^{:.1} guesses",
+            self.line, self.score, self.guesses_log10
+        )?;
+        writeln!(
+            f,
+            "  crack time: {} online throttled, {} online, {} offline slow hash, {} offline fast \
+             hash",
+            self.online_throttled,
+            self.online_unthrottled,
+            self.offline_slow_hash,
+            self.offline_fast_hash
+        )?;
+        if let Some(warning) = &self.warning {
+            writeln!(f, "  warning: {warning}")?;
+        }
+        for suggestion in &self.suggestions {
+            writeln!(f, "  suggestion: {suggestion}")?;
+        }
+        Ok(())
+    }
+}
+
+/// 逐行评估 `input`（`-` 是标准输入）里的候选密码，空行跳过。
+///
+/// `user_inputs` 是和这个用户相关的词（用户名、邮箱、姓名），密码里出现它们会被降分；
+/// 邮箱这类带 `@`、`.` 的还会拆开，每一段各算一个词。
+///
+/// # Errors
+///
+/// 输入读不了时返回错误。
+pub async fn process_password_check(
+    input: &str,
+    user_inputs: &[String],
+) -> Result<Vec<StrengthReport>> {
+    let mut words: Vec<&str> = Vec::new();
+    for input in user_inputs {
+        words.push(input);
+        words.extend(
+            input
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|part| !part.is_empty() && part != input),
+        );
+    }
+
+    let mut lines = BufReader::new(get_reader(input).await?).lines();
+    let mut reports = Vec::new();
+    let mut line = 0;
+    while let Some(password) = lines.next_line().await? {
+        line += 1;
+        let password = password.strip_suffix('\r').unwrap_or(&password);
+        if password.is_empty() {
+            continue;
+        }
+        reports.push(StrengthReport::new(line, &zxcvbn(password, &words)));
+    }
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GenPassOptions, OutputFormat, records_to_string};
+
+    #[tokio::test]
+    async fn test_generated_password_as_csv() -> Result<()> {
+        let opts = GenPassOptions {
+            length: 10,
+            charset: Some("abcd".to_owned()),
+            ..GenPassOptions::default()
+        };
+        // 10 × log2(4)
+        assert!((opts.entropy()? - 20.0).abs() < 1e-9);
+
+        let entries = [
+            GeneratedPassword::new("password".to_owned(), 37.64),
+            GeneratedPassword::new("correct-horse-battery-staple".to_owned(), 51.7),
+        ];
+        assert_eq!(entries[0].score, 0);
+        assert_eq!(entries[0].entropy, 37.6);
+        assert_eq!(entries[0].crack_time, "less than a second");
+        let csv = records_to_string(OutputFormat::Csv, &entries).await?;
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("password,score,entropy,crack_time"));
+        assert_eq!(lines.next(), Some("password,0,37.6,less than a second"));
+        assert!(
+            lines
+                .next()
+                .is_some_and(|line| line.starts_with("correct-horse-battery-staple,"))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_generated_streams_records() -> Result<()> {
+        let mut n = 0;
+        let mut generate = || {
+            n += 1;
+            Ok((format!("pw{n}"), 10.0))
+        };
+        let plain = write_generated(Vec::new(), 2, None, &mut generate).await?;
+        assert_eq!(String::from_utf8(plain)?, "pw1\npw2\n");
+        let ndjson =
+            write_generated(Vec::new(), 1, Some(OutputFormat::Ndjson), &mut generate).await?;
+        assert!(
+            String::from_utf8(ndjson)?
+                .starts_with(r#"{"password":"pw3","score":0,"entropy":10.0,"#)
+        );
+        assert!(
+            write_generated(Vec::new(), 1, Some(OutputFormat::Parquet), &mut generate)
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_password_check_uses_user_inputs() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("candidates.txt");
+        std::fs::write(&input, "password\r\n\nzorblaxquint1990\n")?;
+        let input = input.to_string_lossy();
+
+        let plain = process_password_check(&input, &[]).await?;
+        assert_eq!(plain.len(), 2);
+        assert_eq!((plain[0].line, plain[0].score), (1, 0));
+        assert!(plain[0].warning.is_some());
+        assert_eq!(plain[1].line, 3);
+
+        // 邮箱拆开后 zorblax、quint 都算已知词
+        let personal =
+            process_password_check(&input, &["zorblax.quint@example.com".to_owned()]).await?;
+        assert!(personal[1].score < plain[1].score);
+        assert!(personal[1].to_string().starts_with("line 3: score "));
+        Ok(())
+    }
+}
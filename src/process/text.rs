@@ -256,7 +256,10 @@ async fn load(path: impl AsRef<Path>) -> Result<Self>
 
 impl KeyGenerator for Blake3 {
     fn generate() -> Result<Vec<Vec<u8>>> {
-        let key = gen_pass::process_genpass(32, false, false, false, false)?;
+        let key = gen_pass::process_genpass(&gen_pass::GenPassOptions {
+            length: 32,
+            ..gen_pass::GenPassOptions::default()
+        })?;
         let key = key.trim();
         let key = key.as_bytes().to_vec();
         Ok(vec![key])
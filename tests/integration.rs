@@ -157,3 +157,422 @@ fn base64_encode_then_decode_roundtrip() {
         .success()
         .stdout(contains("hello"));
 }
+
+/// `csv` 转出去再 `csv from` 转回来，表头顺序与内容都不变。
+#[test]
+fn csv_to_toml_then_from_roundtrip() {
+    let dir = TempDir::new().expect("创建临时目录");
+    let toml = dir.path().join("players.toml");
+    let back = dir.path().join("back.csv");
+
+    rcli()
+        .args(["csv", "-i", "assets/juventus.csv", "--format", "toml", "-o"])
+        .arg(&toml)
+        .assert()
+        .success();
+    rcli()
+        .args(["csv", "from", "-i"])
+        .arg(&toml)
+        .arg("-o")
+        .arg(&back)
+        .assert()
+        .success();
+
+    let original = std::fs::read_to_string("assets/juventus.csv").expect("读取示例 CSV");
+    let back = std::fs::read_to_string(&back).expect("读取还原的 CSV");
+    assert_eq!(back.replace("\r\n", "\n"), original.replace("\r\n", "\n"));
+}
+
+/// `--infer` 转出的 TOML 日期是原生的日期时间，`csv from` 要还原成原来的文本。
+#[test]
+fn csv_infer_toml_dates_roundtrip() {
+    let dir = TempDir::new().expect("创建临时目录");
+    let toml = dir.path().join("d.toml");
+    let csv = "id,since\n1,2024-01-31\n2,2023-12-01T08:30:00Z\n";
+
+    rcli()
+        .args(["csv", "-i", "-", "--infer", "--format", "toml", "-o"])
+        .arg(&toml)
+        .write_stdin(csv)
+        .assert()
+        .success();
+    let written = std::fs::read_to_string(&toml).expect("读取 TOML");
+    assert!(
+        written.contains("since = 2024-01-31\n"),
+        "日期要写成 TOML 原生日期"
+    );
+
+    rcli()
+        .args(["csv", "from", "-o", "-", "-i"])
+        .arg(&toml)
+        .assert()
+        .success()
+        .stdout(csv);
+}
+
+/// `-i -` 读标准输入、`-o -` 写标准输出，能夹在管道中间用。
+#[test]
+fn csv_reads_stdin_and_writes_stdout() {
+    rcli()
+        .args(["csv", "-i", "-", "-o", "-", "--format", "ndjson", "--infer"])
+        .write_stdin("name,kit\nBuffon,1\nChiellini,3\n")
+        .assert()
+        .success()
+        .stdout("{\"name\":\"Buffon\",\"kit\":1}\n{\"name\":\"Chiellini\",\"kit\":3}\n");
+}
+
+/// `csv --nested` 拼出嵌套结构，`csv from --nested` 再拆回原来的表。
+#[test]
+fn csv_nested_roundtrip() {
+    let csv = "name,address.city,tags[0],tags[1]\nJuve,Turin,a,b\nToro,Turin,c,\n";
+    let json = rcli()
+        .args([
+            "csv", "-i", "-", "-o", "-", "--format", "ndjson", "--nested",
+        ])
+        .write_stdin(csv)
+        .assert()
+        .success()
+        .stdout(concat!(
+            r#"{"name":"Juve","address":{"city":"Turin"},"tags":["a","b"]}"#,
+            "\n",
+            r#"{"name":"Toro","address":{"city":"Turin"},"tags":["c"]}"#,
+            "\n",
+        ))
+        .get_output()
+        .stdout
+        .clone();
+
+    rcli()
+        .args(["csv", "from", "-i", "-", "-o", "-", "--format", "ndjson"])
+        .arg("--nested")
+        .write_stdin(json)
+        .assert()
+        .success()
+        .stdout(csv);
+}
+
+/// 分号分隔的 Latin-1 输入不给参数也能读对，`--print-dialect` 把猜出的方言打到 stderr。
+#[test]
+fn csv_detects_dialect_and_transcodes() {
+    rcli()
+        .args(["csv", "-i", "-", "-o", "-", "--format", "ndjson"])
+        .arg("--print-dialect")
+        .write_stdin(&b"Name;Stadt\nJos\xe9;M\xfcnchen\n"[..])
+        .assert()
+        .success()
+        .stdout("{\"Name\":\"José\",\"Stadt\":\"München\"}\n")
+        .stderr("-: delimiter ';', quote '\"', header yes, encoding windows-1252\n");
+}
+
+/// `--where` 按原表头过滤行，再经 `--select` 挑列；列名写错时报错并标出位置。
+#[test]
+fn csv_where_filters_rows() {
+    rcli()
+        .args([
+            "csv",
+            "-i",
+            "assets/juventus.csv",
+            "-o",
+            "-",
+            "--format",
+            "ndjson",
+        ])
+        .args(["--where", r#"Position == "Goalkeeper" && Kit Number < 30"#])
+        .args(["--select", "Name"])
+        .assert()
+        .success()
+        .stdout("{\"Name\":\"Wojciech Szczesny\"}\n");
+
+    rcli()
+        .args(["csv", "-i", "assets/juventus.csv", "-o", "-"])
+        .args(["--where", "Kit Nmber < 30"])
+        .assert()
+        .failure()
+        .stderr(contains("  Kit Nmber < 30\n  ^^^^^^^^^"));
+}
+
+/// `csv validate` 有违规时非零退出，`--json` 输出的报告能直接给 CI 解析。
+#[test]
+fn csv_validate_exits_nonzero_on_violations() {
+    let dir = TempDir::new().expect("创建临时目录");
+    let schema = dir.path().join("schema.yaml");
+    std::fs::write(
+        &schema,
+        "columns:\n  Kit Number: { type: integer, unique: true }\n  Position: { allowed: \
+         [Goalkeeper] }\n",
+    )
+    .expect("写 schema");
+
+    rcli()
+        .args([
+            "csv",
+            "validate",
+            "-i",
+            "assets/juventus.csv",
+            "--json",
+            "--schema",
+        ])
+        .arg(&schema)
+        .assert()
+        .failure()
+        .stdout(contains("\"valid\": false"))
+        .stdout(contains("\"rule\": \"allowed\""))
+        .stderr(contains("violation(s) in 27 row(s)"));
+
+    std::fs::write(
+        &schema,
+        "columns:\n  Kit Number: { type: integer, unique: true }\n",
+    )
+    .expect("写 schema");
+    rcli()
+        .args(["csv", "validate", "-i", "assets/juventus.csv", "--schema"])
+        .arg(&schema)
+        .assert()
+        .success()
+        .stdout("");
+}
+
+/// `--infer --format parquet` 写出带类型的列，`csv from` 读回来内容不变。
+#[test]
+fn csv_parquet_roundtrip() {
+    let dir = TempDir::new().expect("创建临时目录");
+    let parquet = dir.path().join("players.parquet");
+    let csv = "name,kit,joined\nBuffon,1,2001-07-03T10:00:00Z\nPerin,,2016-07-01T09:30:00Z\n";
+
+    rcli()
+        .args(["csv", "-i", "-", "--infer", "--format", "parquet", "-o"])
+        .arg(&parquet)
+        .write_stdin(csv)
+        .assert()
+        .success();
+    rcli()
+        .args(["csv", "from", "-i"])
+        .arg(&parquet)
+        .args(["-o", "-"])
+        .assert()
+        .success()
+        .stdout(csv);
+}
+
+/// `--format xlsx` 写出工作簿，再拿它当输入读回来，带类型的列照样推断得出。
+#[test]
+fn csv_xlsx_roundtrip() {
+    let dir = TempDir::new().expect("创建临时目录");
+    let xlsx = dir.path().join("players.xlsx");
+
+    rcli()
+        .args(["csv", "-i", "-", "--infer", "--format", "xlsx", "-o"])
+        .arg(&xlsx)
+        .write_stdin("name,kit,born\nBuffon,1,1978-01-28\nPerin,,1992-11-10\n")
+        .assert()
+        .success();
+    rcli()
+        .args(["csv", "-i"])
+        .arg(&xlsx)
+        .args([
+            "--sheet", "Sheet1", "--infer", "--format", "ndjson", "-o", "-",
+        ])
+        .arg("--print-dialect")
+        .assert()
+        .success()
+        .stdout(concat!(
+            r#"{"name":"Buffon","kit":1,"born":"1978-01-28"}"#,
+            "\n",
+            r#"{"name":"Perin","kit":null,"born":"1992-11-10"}"#,
+            "\n",
+        ))
+        .stderr(contains("sheet \"Sheet1\", header yes"));
+}
+
+/// `--sort-by` 按数值排序，`--dedup` 在排好的顺序里保留每个键的第一行。
+#[test]
+fn csv_sort_and_dedup() {
+    rcli()
+        .args([
+            "csv",
+            "-i",
+            "assets/juventus.csv",
+            "-o",
+            "-",
+            "--format",
+            "csv",
+        ])
+        .args(["--where", r#"Position == "Goalkeeper""#])
+        .args([
+            "--sort-by",
+            "Kit Number:desc",
+            "--select",
+            "Name,Kit Number",
+        ])
+        .assert()
+        .success()
+        .stdout(concat!(
+            "Name,Kit Number\n",
+            "Gianluigi Buffon,77\n",
+            "Mattia Perin,37\n",
+            "Carlo Pinsoglio,31\n",
+            "Wojciech Szczesny,1\n",
+        ));
+
+    rcli()
+        .args(["csv", "-i", "-", "-o", "-", "--format", "csv"])
+        .args(["--sort-by", "team,kit", "--dedup", "team"])
+        .write_stdin("name,team,kit\nBuffon,Juve,77\nPerin,Genoa,1\nSzczesny,Juve,1\n")
+        .assert()
+        .success()
+        .stdout("name,team,kit\nPerin,Genoa,1\nSzczesny,Juve,1\n");
+}
+
+/// `csv diff` 默认输出给人看的报告，`--format` 输出每处差异一条记录。
+#[test]
+fn csv_diff_by_key() {
+    let dir = TempDir::new().expect("创建临时目录");
+    let old = dir.path().join("old.csv");
+    let new = dir.path().join("new.csv");
+    std::fs::write(&old, "Name,Kit Number\nBuffon,1\nPerin,37\nChiellini,3\n").expect("写入旧版本");
+    std::fs::write(&new, "Name;Kit Number\nPerin;37\nBuffon;77\nKean;18\n").expect("写入新版本");
+
+    rcli()
+        .args(["csv", "diff"])
+        .args([&old, &new])
+        .args(["--key", "Name"])
+        .assert()
+        .success()
+        .stdout(concat!(
+            "~ Name=Buffon\n",
+            "    Kit Number: \"1\" -> \"77\"\n",
+            "+ Name=Kean\n",
+            "- Name=Chiellini\n",
+            "1 added, 1 removed, 1 changed, 1 unchanged\n",
+        ));
+
+    rcli()
+        .args(["csv", "diff"])
+        .args([&old, &new])
+        .args(["--key", "1", "--format", "ndjson"])
+        .assert()
+        .success()
+        .stdout(concat!(
+            r#"{"change":"changed","key":{"Name":"Buffon"},"fields":[{"column":"Kit Number","old":"1","new":"77"}]}"#,
+            "\n",
+            r#"{"change":"added","key":{"Name":"Kean"},"row":{"Name":"Kean","Kit Number":"18"}}"#,
+            "\n",
+            r#"{"change":"removed","key":{"Name":"Chiellini"},"row":{"Name":"Chiellini","Kit Number":"3"}}"#,
+            "\n",
+        ));
+}
+
+/// `csv split --by` 按列值一份一个文件，每份都带表头；标准输出列出各份和行数。
+#[test]
+fn csv_split_by_column() {
+    let dir = TempDir::new().expect("创建临时目录");
+    rcli()
+        .args([
+            "csv",
+            "split",
+            "-i",
+            "assets/juventus.csv",
+            "--by",
+            "Position",
+        ])
+        .args(["--name", "{n:02}-{value}.csv", "-o"])
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(contains("01-Goalkeeper.csv\t4\n"));
+
+    let keepers = std::fs::read_to_string(dir.path().join("01-Goalkeeper.csv")).expect("读第一份");
+    assert!(keepers.starts_with("Name,Position,DOB,Nationality,Kit Number\nWojciech Szczesny,"));
+    assert_eq!(keepers.lines().count(), 5);
+}
+
+#[test]
+fn genpass_check_min_score() {
+    // 生成密码的参数不能和 check 混用，check 走的是子命令
+    rcli()
+        .args(["genpass", "check", "--min-score", "3"])
+        .write_stdin("correct horse battery staple\n")
+        .assert()
+        .success()
+        .stdout(contains("line 1: score 4/4"));
+
+    rcli()
+        .args(["genpass", "check", "--min-score", "3", "-u", "zorblax"])
+        .write_stdin("zorblax2024\ncorrect horse battery staple\n")
+        .assert()
+        .failure()
+        .stderr(contains("1 of 2 password(s) scored below 3 (line 1)"));
+
+    rcli()
+        .args(["genpass", "-l", "20", "check"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn genpass_count_and_format() {
+    let out = rcli()
+        .args(["genpass", "-n", "5", "--policy", "mainframe"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(out.get_output().stdout.clone()).expect("密码是 UTF-8");
+    assert_eq!(stdout.lines().count(), 5);
+    assert!(stdout.lines().all(|line| line.len() == 8));
+
+    let out = rcli()
+        .args(["genpass", "-n", "3", "--format", "csv"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(out.get_output().stdout.clone()).expect("CSV 是 UTF-8");
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("password,score,entropy,crack_time"));
+    assert_eq!(lines.count(), 3);
+
+    rcli()
+        .args(["genpass", "-n", "2", "--format", "parquet"])
+        .assert()
+        .failure()
+        .stderr(contains("binary"));
+}
+
+#[test]
+fn genpass_length_follows_policy() {
+    let out = rcli().args(["genpass", "-l", "300"]).assert().success();
+    let stdout = String::from_utf8(out.get_output().stdout.clone()).expect("密码是 UTF-8");
+    assert_eq!(stdout.trim_end().len(), 300);
+
+    rcli()
+        .args(["genpass", "-l", "6"])
+        .assert()
+        .failure()
+        .stderr(contains("length 6 is too short"));
+
+    // 离谱的长度在解析参数时就拦下，不会去分配那么大的字符串
+    rcli()
+        .args(["genpass", "-l", "18446744073709551615"])
+        .assert()
+        .failure()
+        .stderr(contains("invalid value"));
+
+    // 策略说了算：允许 6 位就能生成 6 位，超出 max_length 报错
+    let dir = TempDir::new().expect("创建临时目录");
+    let policy = dir.path().join("pin.yaml");
+    std::fs::write(
+        &policy,
+        "min_length: 6\nmax_length: 6\ncharset: '0123456789'\n",
+    )
+    .expect("写策略");
+    let out = rcli()
+        .args(["genpass", "-l", "6", "--policy"])
+        .arg(&policy)
+        .assert()
+        .success();
+    let stdout = String::from_utf8(out.get_output().stdout.clone()).expect("密码是 UTF-8");
+    assert_eq!(stdout.trim_end().len(), 6);
+
+    rcli()
+        .args(["genpass", "--policy", "active-directory", "-l", "257"])
+        .assert()
+        .failure()
+        .stderr(contains("8 to 256"));
+}